@@ -0,0 +1,79 @@
+use std::ops::Add;
+use std::sync::Arc;
+use chrono::{Duration, Local};
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use phi_accrual_detector::{Detector, PhiInteraction};
+
+fn warmed_detector(runtime: &tokio::runtime::Runtime) -> Arc<Detector> {
+    let detector = Arc::new(Detector::new(1000));
+    runtime.block_on(async {
+        let mut t = Local::now();
+        for i in 0..1000 {
+            detector.insert(t).await.unwrap();
+            t = t.add(Duration::milliseconds(100 + i % 7));
+        }
+    });
+    detector
+}
+
+fn phi(c: &mut Criterion) {
+    let runtime = tokio::runtime::Builder::new_multi_thread().enable_all().build().unwrap();
+    let detector = warmed_detector(&runtime);
+    let t = Local::now();
+
+    c.bench_function("phi", |b| {
+        b.to_async(&runtime).iter(|| detector.phi(t))
+    });
+
+    // a task inserting back to back keeps the write lock busy while phi is measured
+    let writer = Arc::clone(&detector);
+    let inserting = runtime.spawn(async move {
+        loop {
+            writer.insert(Local::now()).await.unwrap();
+            tokio::task::yield_now().await;
+        }
+    });
+    c.bench_function("phi with concurrent inserts", |b| {
+        b.to_async(&runtime).iter(|| detector.phi(t))
+    });
+    inserting.abort();
+}
+
+// the window mean as previously computed, dividing every element by the length
+fn mean_divide_each(intervals: &[u64]) -> f64 {
+    let mut mean = 0.;
+    let len = intervals.len();
+    for v in intervals {
+        mean += *v as f64 / len as f64;
+    }
+    mean
+}
+
+// the window mean as now computed, summing then dividing once
+fn mean_sum_then_divide(intervals: &[u64]) -> f64 {
+    let sum: f64 = intervals.iter().map(|v| *v as f64).sum();
+    sum / intervals.len() as f64
+}
+
+fn mean(c: &mut Criterion) {
+    let intervals: Vec<u64> = (0..10000).map(|i| 100 + i % 7).collect();
+    let mut group = c.benchmark_group("mean of 10000 intervals");
+    group.bench_function("divide each", |b| b.iter(|| mean_divide_each(black_box(&intervals))));
+    group.bench_function("sum then divide", |b| b.iter(|| mean_sum_then_divide(black_box(&intervals))));
+    group.finish();
+
+    // every insert recomputes the distribution for the cache, so a full window shows the mean
+    let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+    let detector = Detector::new(10000);
+    runtime.block_on(async {
+        for i in 0..intervals.len() as u64 {
+            detector.insert_interval(100 + i % 7).await.unwrap();
+        }
+    });
+    c.bench_function("insert_interval into 10000 window", |b| {
+        b.to_async(&runtime).iter(|| detector.insert_interval(100))
+    });
+}
+
+criterion_group!(benches, phi, mean);
+criterion_main!(benches);
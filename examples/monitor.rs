@@ -1,4 +1,4 @@
-use std::fs::{OpenOptions};
+use std::fs::{File, OpenOptions};
 use std::io::Write;
 use std::sync::{Arc};
 use std::thread;
@@ -8,15 +8,22 @@ use std::time::Duration;
 use async_trait::async_trait;
 use chrono::{DateTime, Local, TimeDelta};
 use rand::Rng;
+use serde::{Serialize, Serializer};
 use tokio::sync::RwLock;
+use tracing::info;
 use phi_accrual_detector::{Detector, PhiInteraction};
 
-#[derive(Debug)]
+#[derive(Debug, Serialize)]
 struct HistoryElement {
     phi: f64,
+    #[serde(serialize_with = "serialize_rfc3339")]
     time: DateTime<Local>,
 }
 
+fn serialize_rfc3339<S: Serializer>(time: &DateTime<Local>, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&time.to_rfc3339())
+}
+
 struct Monitor {
     detector: Arc<Detector>,
     history: RwLock<Vec<HistoryElement>>,
@@ -26,8 +33,11 @@ struct Monitor {
 trait MonitorInteraction {
     async fn ping(&self);
     async fn suspicion(&self) -> f64;
+    #[allow(dead_code)]
     async fn show_history(&self);
-    async fn publish_csv(&self, filename: &str);
+    async fn drain_history(&self) -> Vec<HistoryElement>;
+    async fn publish_json(&self, filename: &str);
+    async fn publish(&self, csv_path: &str, json_path: &str);
 }
 
 impl Monitor {
@@ -45,40 +55,126 @@ impl MonitorInteraction for Monitor {
 
     async fn suspicion(&self) -> f64 {
         let current_time = Local::now();
-        let last_arrived_at = self.detector.last_arrived_at().await.expect("Some panic occurred");
+        let last_arrived_at = match self.detector.last_arrived_at().await {
+            Ok(last_arrived_at) => last_arrived_at,
+            Err(_) => {
+                info!("no heartbeat yet");
+                return 0.;
+            }
+        };
         let phi = self.detector.phi(current_time).await.unwrap();
         let mut history = self.history.write().await;
-        println!("suspicion: {:?} when last ping was at : {:?}", phi, last_arrived_at);
+        info!(phi, %last_arrived_at, "suspicion");
         history.push(HistoryElement { phi, time: current_time });
         phi
     }
 
     async fn show_history(&self) {
         let history = self.history.read().await;
-        println!("Suspicion History: {:?}", history);
+        info!(?history, "suspicion history");
     }
 
-    async fn publish_csv(&self, file_path: &str) {
-        let mut file = OpenOptions::new()
+    /// Take the accumulated history, leaving it empty so it doesn't grow for the lifetime of the
+    /// monitor.
+    async fn drain_history(&self) -> Vec<HistoryElement> {
+        std::mem::take(&mut *self.history.write().await)
+    }
+
+    /// Write the history to file_path as a JSON array, replacing what the file held.
+    async fn publish_json(&self, file_path: &str) {
+        let file = OpenOptions::new()
             .write(true)
             .create(true)
+            .truncate(true)
             .open(file_path)
             .unwrap();
         let history = self.history.read().await;
+        serde_json::to_writer(file, &*history).unwrap();
+        info!(file_path, "json metrics published");
+    }
 
-        for element in history.iter() {
-            let line = format!("{},{}\n", element.phi, element.time.format("%M:%S:%.6f"));
-            file.write_all(line.as_bytes()).unwrap();
+    /// Write the history to json_path with publish_json, then append it to csv_path. Published
+    /// rows are consumed: they are drained from the history, so each row is written to the CSV
+    /// once and the JSON holds the rows since the previous publish.
+    async fn publish(&self, csv_path: &str, json_path: &str) {
+        self.publish_json(json_path).await;
+        let history = self.drain_history().await;
+        publish_csv(csv_path, &history);
+    }
+}
+
+fn publish_csv(file_path: &str, history: &[HistoryElement]) {
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(file_path)
+        .unwrap();
+    for element in history {
+        let line = format!("{},{}\n", element.phi, element.time.format("%M:%S:%.6f"));
+        file.write_all(line.as_bytes()).unwrap();
+    }
+    info!(file_path, "metrics published");
+}
+
+/// How the simulated node heartbeats, picked with the first argument or the MONITOR_MODE
+/// environment variable: `cargo run --example monitor -- degrade`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Mode {
+    /// Uniformly random delays until a random shutdown, the default.
+    Shutdown,
+    /// Rounds of pings whose jitter grows through the round, with occasional long pauses, then
+    /// recovers at the start of the next round, before shutting down after the last round.
+    Degrade,
+}
+
+/// Pings per round in degrade mode.
+const DEGRADE_ROUND: u64 = 50;
+const DEGRADE_ROUNDS: u64 = 4;
+
+impl Mode {
+    fn from_args() -> Self {
+        let mode = std::env::args().nth(1).or_else(|| std::env::var("MONITOR_MODE").ok());
+        match mode.as_deref() {
+            None | Some("shutdown") => Mode::Shutdown,
+            Some("degrade") => Mode::Degrade,
+            Some(other) => panic!("unknown mode {:?}, expected shutdown or degrade", other),
+        }
+    }
+
+    /// Delay in ms before the i-th ping, None once the node shuts down.
+    fn next_delay(self, i: u64) -> Option<u64> {
+        let mut rng = rand::rng();
+        match self {
+            Mode::Shutdown => {
+                let dur = rng.random_range(100..1000);
+                (dur <= 950).then_some(dur)
+            }
+            Mode::Degrade => {
+                if i >= DEGRADE_ROUND * DEGRADE_ROUNDS {
+                    return None;
+                }
+                if rng.random_bool(0.03) {
+                    return Some(rng.random_range(2000..4000));
+                }
+                let jitter = 10 + 8 * (i % DEGRADE_ROUND);
+                Some(300 - jitter.min(290) + rng.random_range(0..2 * jitter))
+            }
         }
-        println!("metrics published");
     }
 }
 
 #[tokio::main]
 async fn main() {
+    // run with --features tracing to include the detector's own debug events
+    tracing_subscriber::fmt().with_max_level(tracing::Level::DEBUG).init();
+    // start each run with an empty CSV, it is appended to from then on
+    File::create("history.csv").unwrap();
+
     let detector = Arc::new(Detector::with_acceptable_pause(1000, TimeDelta::milliseconds(1000)));
     let monitor = Arc::new(Monitor::new(detector.clone()));
     let monitor_phi = Arc::clone(&monitor);
+    let mode = Mode::from_args();
+    info!(?mode, "simulating node");
 
     let ping_thread = thread::spawn(move || {
         tokio::runtime::Builder::new_current_thread()
@@ -86,15 +182,14 @@ async fn main() {
             .build()
             .unwrap()
             .block_on(async {
-                loop {
-                    let dur = rand::thread_rng().gen_range(100..1000);
-                    if dur > 950 {
-                        println!("Simulating shutdown at: {:?}", Local::now().to_rfc3339());
+                for i in 0.. {
+                    let Some(dur) = mode.next_delay(i) else {
+                        info!(at = %Local::now().to_rfc3339(), "simulating shutdown");
                         break;
-                    }
+                    };
                     // Simulate the "ping" process
                     task::sleep(Duration::from_millis(dur)).await;
-                    println!("Pinging the monitor");
+                    info!("pinging the monitor");
                     monitor.ping().await;
                 }
             });
@@ -113,7 +208,7 @@ async fn main() {
                     let p = monitor_phi.suspicion().await;
                     if i % 10 == 0 {
                         // monitor_phi.show_history().await;
-                        monitor_phi.publish_csv("history.csv").await;
+                        monitor_phi.publish("history.csv", "history.json").await;
                         if p == f64::INFINITY {
                             break;
                         }
@@ -125,4 +220,53 @@ async fn main() {
 
     ping_thread.join().unwrap();
     phi_thread.join().unwrap();
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_drain_history_empties_history() {
+        let detector = Arc::new(Detector::new(10));
+        let monitor = Monitor::new(detector);
+        monitor.ping().await;
+        monitor.suspicion().await;
+        monitor.suspicion().await;
+
+        assert_eq!(2, monitor.drain_history().await.len());
+        assert!(monitor.history.read().await.is_empty());
+        assert!(monitor.drain_history().await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_publish_drains_history() {
+        let detector = Arc::new(Detector::new(10));
+        let monitor = Monitor::new(detector);
+        monitor.ping().await;
+        monitor.suspicion().await;
+        monitor.suspicion().await;
+
+        let dir = std::env::temp_dir();
+        let csv_path = dir.join(format!("monitor-{}.csv", std::process::id()));
+        let json_path = dir.join(format!("monitor-{}.json", std::process::id()));
+        let (csv_path, json_path) = (csv_path.to_str().unwrap(), json_path.to_str().unwrap());
+        monitor.publish(csv_path, json_path).await;
+        assert!(monitor.history.read().await.is_empty());
+        let json: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(json_path).unwrap()).unwrap();
+        assert_eq!(2, json.as_array().unwrap().len());
+        assert_eq!(2, std::fs::read_to_string(csv_path).unwrap().lines().count());
+        std::fs::remove_file(csv_path).unwrap();
+        std::fs::remove_file(json_path).unwrap();
+    }
+
+    #[test]
+    fn test_degrade_jitter_grows_within_round() {
+        // the first ping of a round is within 10ms of 300ms, the last may be off by hundreds
+        let delays: Vec<u64> = (0..1000).filter_map(|_| Mode::Degrade.next_delay(0)).collect();
+        assert!(delays.iter().all(|d| (290..310).contains(d) || *d >= 2000));
+        let delays: Vec<u64> = (0..1000).filter_map(|_| Mode::Degrade.next_delay(DEGRADE_ROUND - 1)).collect();
+        assert!(delays.iter().any(|d| !(200..400).contains(d)));
+        assert_eq!(None, Mode::Degrade.next_delay(DEGRADE_ROUND * DEGRADE_ROUNDS));
+    }
+}
@@ -0,0 +1,119 @@
+//! tower integration, see PhiHealthLayer.
+
+use std::error::Error;
+use std::future::Future;
+use std::hash::Hash;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use tower::{Layer, Service};
+use crate::{DetectorRegistry, PhiError};
+
+/// Layer gating requests on the health of the node they are destined for, like a circuit
+/// breaker driven by the registry's detectors. key extracts the node from a request; a request
+/// whose node is suspected by its detector fails with PhiError::NodeSuspected without reaching
+/// the inner service. Nodes not in the registry or without a heartbeat yet pass through.
+#[derive(Debug)]
+pub struct PhiHealthLayer<K, F> {
+    registry: Arc<DetectorRegistry<K>>,
+    key: F,
+}
+
+impl<K, F: Clone> Clone for PhiHealthLayer<K, F> {
+    fn clone(&self) -> Self {
+        PhiHealthLayer { registry: Arc::clone(&self.registry), key: self.key.clone() }
+    }
+}
+
+impl<K, F> PhiHealthLayer<K, F> {
+    /// New PhiHealthLayer checking the nodes key extracts from requests against registry.
+    pub fn new(registry: Arc<DetectorRegistry<K>>, key: F) -> Self {
+        PhiHealthLayer { registry, key }
+    }
+}
+
+impl<S, K, F: Clone> Layer<S> for PhiHealthLayer<K, F> {
+    type Service = PhiHealthService<S, K, F>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        PhiHealthService { inner, registry: Arc::clone(&self.registry), key: self.key.clone() }
+    }
+}
+
+/// Service built by PhiHealthLayer.
+#[derive(Debug)]
+pub struct PhiHealthService<S, K, F> {
+    inner: S,
+    registry: Arc<DetectorRegistry<K>>,
+    key: F,
+}
+
+impl<S: Clone, K, F: Clone> Clone for PhiHealthService<S, K, F> {
+    fn clone(&self) -> Self {
+        PhiHealthService { inner: self.inner.clone(), registry: Arc::clone(&self.registry), key: self.key.clone() }
+    }
+}
+
+impl<S, K, F, Req> Service<Req> for PhiHealthService<S, K, F>
+where
+    S: Service<Req> + Clone + Send + 'static,
+    S::Future: Send,
+    S::Error: Into<Box<dyn Error + Send + Sync>>,
+    K: Eq + Hash + Clone + Send + Sync + 'static,
+    F: Fn(&Req) -> K,
+    Req: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = Box<dyn Error + Send + Sync>;
+    type Future = Pin<Box<dyn Future<Output = Result<S::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, req: Req) -> Self::Future {
+        let node = (self.key)(&req);
+        let registry = Arc::clone(&self.registry);
+        // the clone isn't necessarily ready, keep the one poll_ready was called on
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        Box::pin(async move {
+            if let Some(detector) = registry.get(&node).await {
+                let now = detector.config.clock.now();
+                if detector.is_suspected(now).await.unwrap_or(false) {
+                    return Err(PhiError::NodeSuspected.into());
+                }
+            }
+            inner.call(req).await.map_err(Into::into)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::Infallible;
+    use std::sync::Arc;
+    use chrono::{Local, TimeDelta};
+    use tower::{service_fn, Layer, ServiceExt};
+    use crate::{DetectorRegistry, PhiError, PhiHealthLayer};
+
+    #[tokio::test]
+    async fn test_rejects_suspected_nodes() {
+        let registry = Arc::new(DetectorRegistry::new(10, TimeDelta::zero()));
+        let t = Local::now() - TimeDelta::seconds(10);
+        for ms in [0, 100, 200, 300] {
+            registry.insert("stale", t + TimeDelta::milliseconds(ms)).await.unwrap();
+        }
+        let now = Local::now();
+        for ms in [-300, -200, -100, 0] {
+            registry.insert("healthy", now + TimeDelta::milliseconds(ms)).await.unwrap();
+        }
+
+        let layer = PhiHealthLayer::new(Arc::clone(&registry), |req: &(&'static str, u32)| req.0);
+        let service = layer.layer(service_fn(|req: (&'static str, u32)| async move { Ok::<_, Infallible>(req.1 * 2) }));
+        assert_eq!(42, service.clone().oneshot(("healthy", 21)).await.unwrap());
+        assert_eq!(2, service.clone().oneshot(("unknown", 1)).await.unwrap());
+        let err = service.oneshot(("stale", 21)).await.unwrap_err();
+        assert_eq!(Some(&PhiError::NodeSuspected), err.downcast_ref::<PhiError>());
+    }
+}
@@ -37,10 +37,8 @@
 //!    }
 //!}
 //!
-//! fn main() {
-//!   let detector = Arc::new(Detector::new(1000));
-//!   let monitor = Monitor { detector: Arc::clone(&detector) };
-//! }
+//! let detector = Arc::new(Detector::new(1000));
+//! let monitor = Monitor { detector: Arc::clone(&detector) };
 //! ```
 //!
 //! The above example gives you an implementation of a Monitor struct which can be used to interact
@@ -61,237 +59,5117 @@
 //!
 //! // implementation and traits remain the same.
 //!
-//! fn main() {
-//!   let detector = Arc::new(Detector::with_acceptable_pause(1000, TimeDelta::milliseconds(1000)));
-//!   let monitor = Monitor { detector: Arc::clone(&detector) };
-//! }
+//! let detector = Arc::new(Detector::with_acceptable_pause(1000, TimeDelta::milliseconds(1000)));
+//! let monitor = Monitor { detector: Arc::clone(&detector) };
 //! ```
 //!
+use std::collections::VecDeque;
 use std::error::Error;
+use std::fmt;
+use std::io;
 use std::ops::Sub;
 use std::sync::{Arc};
-use tokio::sync::{RwLock, RwLockReadGuard};
+use std::sync::atomic::{AtomicBool, AtomicI64, AtomicU32, AtomicU64, AtomicU8, AtomicUsize, Ordering};
+use std::time::Duration;
+use tokio::sync::{broadcast, mpsc, Notify};
+use runtime::RwLock;
 use async_trait::async_trait;
-use libm::{erf, log10};
-use chrono::{DateTime, Local, TimeDelta};
+use futures::{Stream, StreamExt};
+use libm::{erf, exp, lgamma, log, log10};
+use chrono::{DateTime, Local, TimeDelta, Utc};
+
+#[cfg(feature = "tower")]
+mod layer;
+mod registry;
+mod runtime;
+mod snapshot;
+mod store;
+mod sync;
+mod view;
+
+#[cfg(feature = "tower")]
+pub use layer::{PhiHealthLayer, PhiHealthService};
+pub use registry::{ClusterHealth, DetectorRegistry};
+pub use store::{CompactIntervals, IntervalStore};
+pub use sync::SyncDetector;
+pub use view::DetectorView;
 
 /// Statistics of last window_length intervals
 #[derive(Clone, Debug)]
 pub struct Statistics {
-    arrival_intervals: Vec<u64>,
+    arrival_intervals: Box<dyn IntervalStore>,
+    // arrival time of the heartbeat closing each interval, parallel to arrival_intervals
+    arrival_times: VecDeque<DateTime<Local>>,
     last_arrived_at: DateTime<Local>,
     window_length: u32,
     n: u32,
+    clock_regressions: u64,
+    ewma: Option<Ewma>,
+    unit: IntervalUnit,
+    // subtracted from every interval computed by insert
+    network_offset: TimeDelta,
+    auto_window: Option<AutoWindow>,
+    // consecutive intervals diverging from the window mean, see DetectorBuilder::with_rate_change_detection
+    rate_change_streak: u32,
+    // heartbeats inserted so far, recorded or not, see Detector::current_seq
+    seq: u64,
+}
+
+/// Growth state of an auto-sized window, see DetectorBuilder::with_auto_window.
+#[derive(Clone, Copy, Debug)]
+struct AutoWindow {
+    max_window_length: u32,
+    epsilon: f64,
+    // variance when the window last filled up, None before the first time
+    last_variance: Option<f64>,
+    converged: bool,
+}
+
+/// Exponentially weighted mean and variance of the intervals, updated on every insert.
+#[derive(Clone, Debug)]
+struct Ewma {
+    alpha: f64,
+    mean: f64,
+    variance: f64,
+    samples: u64,
+}
+
+impl Ewma {
+    fn new(alpha: f64) -> Self {
+        assert!(alpha > 0. && alpha <= 1., "EWMA alpha must be within (0, 1], got {}", alpha);
+        Ewma { alpha, mean: 0., variance: 0., samples: 0 }
+    }
+
+    fn update(&mut self, interval: u64) {
+        let x = interval as f64;
+        if self.samples == 0 {
+            self.mean = x;
+            self.variance = 0.;
+        } else {
+            let diff = x - self.mean;
+            let increment = self.alpha * diff;
+            self.mean += increment;
+            self.variance = (1. - self.alpha) * (self.variance + diff * increment);
+        }
+        self.samples += 1;
+    }
 }
 
 /// Detector meant for abstraction over Statistics
-#[derive(Debug)]
 pub struct Detector {
     statistics: RwLock<Statistics>,
-    acceptable_pause: TimeDelta,
+    // microseconds, atomic so the pause can be changed while phi is being computed
+    acceptable_pause: AtomicI64,
+    cache: Cache,
+    config: Config,
+    // one of the OVERRIDE_* states, see Detector::force_down
+    override_state: AtomicU8,
+    // nanoseconds since epoch until which phi is suppressed, NO_GRACE once over
+    grace_until: AtomicI64,
+    // heartbeats queued by insert, Some with DetectorBuilder::with_insert_batching
+    batch: Option<Batch>,
+    // microseconds from the sender's stamp to receipt of the last heartbeat inserted with
+    // insert_with_send_time, NO_DELAY before the first one
+    one_way_delay: AtomicI64,
+    // is_suspected's last verdict, held in the deadband with DetectorBuilder::with_hysteresis
+    suspected: AtomicBool,
+    // woken on every heartbeat to re-arm Detector::watch and Detector::await_warm
+    heartbeat: Notify,
+    // intervals in window as of the last refresh, for the min samples check without the lock
+    samples: AtomicUsize,
+    // the window's length as of the last refresh, which an auto-sized window grows
+    window_length: AtomicU32,
+    // set by Detector::pause, heartbeats then only move last_arrived_at
+    paused: AtomicBool,
+    // heartbeats reported lost by report_missed_heartbeats, halved by every heartbeat since
+    missed: AtomicU32,
+    // verdict changes published to the receivers of Detector::state_events
+    transitions: broadcast::Sender<StateTransition>,
 }
 
-impl Detector {
-    /// New Detector instance with window_length. Recommended window_length is < 10000
-    pub fn new(window_length: u32) -> Self {
-        Detector {
-            statistics: RwLock::new(Statistics::new(window_length)),
-            acceptable_pause: TimeDelta::milliseconds(0),
-        }
+/// Heartbeats queued by insert in batching mode until they are drained into the statistics under
+/// a single write lock. Pushing doesn't lock; the receiver's mutex is only taken by the drainer,
+/// which holds the write lock, so it is never contended.
+#[derive(Debug)]
+struct Batch {
+    tx: mpsc::UnboundedSender<DateTime<Local>>,
+    rx: std::sync::Mutex<mpsc::UnboundedReceiver<DateTime<Local>>>,
+}
+
+impl Batch {
+    fn new() -> Self {
+        let (tx, rx) = mpsc::unbounded_channel();
+        Batch { tx, rx: std::sync::Mutex::new(rx) }
     }
+}
 
-    /// New Detector instance with acceptable heartbeat pause duration.
-    pub fn with_acceptable_pause(window_length: u32, acceptable_pause: TimeDelta) -> Self {
-        Detector {
-            statistics: RwLock::new(Statistics::new(window_length)),
-            acceptable_pause,
-        }
+const OVERRIDE_NONE: u8 = 0;
+const OVERRIDE_DOWN: u8 = 1;
+const OVERRIDE_UP: u8 = 2;
+
+const NO_GRACE: i64 = i64::MIN;
+
+const NO_DELAY: i64 = i64::MIN;
+
+/// Distribution and last arrival, refreshed under the write lock on every change to the statistics
+/// so that phi never has to take the lock. The fields are read independently, so a phi racing an
+/// insert may briefly combine the new arrival with the previous distribution or vice versa.
+#[derive(Debug, Default)]
+struct Cache {
+    // f64 bits
+    mean: AtomicU64,
+    variance: AtomicU64,
+    // nanoseconds since epoch, NO_HEARTBEAT before the first heartbeat and FAR_ARRIVAL for one
+    // outside the years 1677 to 2262 nanoseconds cover, see far_arrived_at
+    last_arrived_at: AtomicI64,
+    // microseconds since epoch of a FAR_ARRIVAL, which cover every DateTime
+    far_arrived_at: AtomicI64,
+}
+
+const NO_HEARTBEAT: i64 = i64::MIN;
+const FAR_ARRIVAL: i64 = i64::MAX;
+
+impl Cache {
+    fn store(&self, variance: f64, mean: f64, last_arrived_at: Option<DateTime<Local>>) {
+        self.mean.store(mean.to_bits(), Ordering::Release);
+        self.variance.store(variance.to_bits(), Ordering::Release);
+        let nanos = match last_arrived_at {
+            Some(last_arrived_at) => last_arrived_at.timestamp_nanos_opt().unwrap_or_else(|| {
+                self.far_arrived_at.store(last_arrived_at.timestamp_micros(), Ordering::Release);
+                FAR_ARRIVAL
+            }),
+            None => NO_HEARTBEAT,
+        };
+        self.last_arrived_at.store(nanos, Ordering::Release);
+    }
+
+    /// Cached variance, mean and last arrival, None before the first heartbeat.
+    fn load(&self) -> Option<(f64, f64, DateTime<Local>)> {
+        let last_arrived_at = match self.last_arrived_at.load(Ordering::Acquire) {
+            NO_HEARTBEAT => return None,
+            // came from a DateTime, so it converts back
+            FAR_ARRIVAL => DateTime::from_timestamp_micros(self.far_arrived_at.load(Ordering::Acquire))?,
+            nanos => DateTime::from_timestamp_nanos(nanos),
+        };
+        Some((
+            f64::from_bits(self.variance.load(Ordering::Acquire)),
+            f64::from_bits(self.mean.load(Ordering::Acquire)),
+            last_arrived_at.with_timezone(&Local),
+        ))
     }
 }
 
-impl Statistics {
-    /// New Statistics instance with window_length.
-    pub fn new(window_length: u32) -> Self {
-        Self {
-            arrival_intervals: vec![],
-            last_arrived_at: Local::now(),
-            window_length,
-            n: 0,
+/// Options shaping how phi is derived from the statistics, set through DetectorBuilder.
+#[derive(Clone, Debug)]
+struct Config {
+    max_phi: Option<f64>,
+    base: PhiBase,
+    prior: Option<Prior>,
+    // fixed distribution phi is computed from instead of the window, see DetectorBuilder::with_sla
+    sla: Option<Prior>,
+    // one interval unit if unset
+    min_std_deviation: Option<TimeDelta>,
+    pause_mode: PauseMode,
+    threshold: f64,
+    adaptive_pause: Option<f64>,
+    // whether the fixed pause is added to the adaptive one, see DetectorBuilder::with_pause_model
+    pause_model_base: bool,
+    probability_threshold: Option<f64>,
+    outlier_rejection: Option<f64>,
+    trimmed_fraction: Option<f64>,
+    median_spread: bool,
+    startup_grace: Option<TimeDelta>,
+    min_samples: usize,
+    clock: Arc<dyn Clock>,
+    min_interval_ms: u64,
+    min_interval_policy: MinIntervalPolicy,
+    interval_unit: IntervalUnit,
+    on_insert: Option<OnInsert>,
+    insert_batching: bool,
+    rate_change: Option<RateChangeDetection>,
+    on_rate_change: Option<OnRateChange>,
+    reference: PhiReference,
+    // variance factor and multiple of the mean, see DetectorBuilder::with_idle_decay
+    idle_decay: Option<(f64, f64)>,
+    // up and down thresholds, see DetectorBuilder::with_hysteresis
+    hysteresis: Option<(f64, f64)>,
+    // phi from which Detector::severity reports Warning, and above which Critical
+    warning_phi: f64,
+    critical_phi: f64,
+    // slope of Detector::health_score's logistic, see DetectorBuilder::with_health_steepness
+    health_steepness: f64,
+    // sample count below which phi uses a Student's t tail, see DetectorBuilder::with_student_t_tail
+    student_t_below: Option<usize>,
+    // node id tagging tracing spans and events and the Debug output, see DetectorBuilder::with_label
+    label: Option<String>,
+}
+
+/// Change-point detection settings, see DetectorBuilder::with_rate_change_detection.
+#[derive(Clone, Copy, Debug)]
+struct RateChangeDetection {
+    factor: f64,
+    samples: u32,
+    reset: bool,
+}
+
+/// Callback handed every detected rate change, see DetectorBuilder::with_on_rate_change.
+#[derive(Clone)]
+struct OnRateChange(Arc<dyn Fn(RateChange) + Send + Sync>);
+
+impl fmt::Debug for OnRateChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("OnRateChange")
+    }
+}
+
+/// Callback handed every recorded interval, see DetectorBuilder::with_on_insert.
+#[derive(Clone)]
+struct OnInsert(Arc<dyn Fn(u64, DateTime<Local>) + Send + Sync>);
+
+impl fmt::Debug for OnInsert {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("OnInsert")
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            max_phi: None,
+            base: PhiBase::default(),
+            prior: None,
+            sla: None,
+            min_std_deviation: None,
+            pause_mode: PauseMode::default(),
+            threshold: DEFAULT_THRESHOLD,
+            adaptive_pause: None,
+            pause_model_base: false,
+            probability_threshold: None,
+            outlier_rejection: None,
+            trimmed_fraction: None,
+            median_spread: false,
+            startup_grace: None,
+            min_samples: 0,
+            clock: Arc::new(SystemClock),
+            min_interval_ms: 0,
+            min_interval_policy: MinIntervalPolicy::default(),
+            interval_unit: IntervalUnit::default(),
+            on_insert: None,
+            insert_batching: false,
+            rate_change: None,
+            on_rate_change: None,
+            reference: PhiReference::default(),
+            idle_decay: None,
+            hysteresis: None,
+            warning_phi: DEFAULT_WARNING_PHI,
+            critical_phi: DEFAULT_THRESHOLD,
+            health_steepness: DEFAULT_HEALTH_STEEPNESS,
+            student_t_below: None,
+            label: None,
         }
     }
+}
 
-    /// Insert heartbeat arrival time in window.
-    pub fn insert(&mut self, arrived_at: DateTime<Local>) {
+/// Source of the current time for the methods that don't take one, such as Detector::phi_now,
+/// so tests can drive a detector deterministically.
+pub trait Clock: fmt::Debug + Send + Sync {
+    fn now(&self) -> DateTime<Local>;
+}
 
-        // insert first element
-        if self.n == 0 {
-            self.last_arrived_at = arrived_at;
-            self.n += 1;
-            return;
+/// Clock reading the system's local time.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Local> {
+        Local::now()
+    }
+}
+
+/// Window length of Detector::default, long enough for a stable distribution while keeping each
+/// detector to a few tens of kilobytes.
+pub const DEFAULT_WINDOW_LENGTH: u32 = 1000;
+
+/// Phi above which a node is reported as suspected, the value commonly used by Akka and Cassandra.
+pub const DEFAULT_THRESHOLD: f64 = 8.;
+
+/// Acceptable pause, in expected intervals, above which DetectorBuilder::build_checked rejects
+/// the config. Pauses of a few intervals are common, a hundred means phi barely reacts at all.
+pub const MAX_PAUSE_INTERVALS: f64 = 100.;
+
+/// Phi from which Detector::severity reports Severity::Warning unless configured otherwise.
+pub const DEFAULT_WARNING_PHI: f64 = 1.;
+
+/// Steepness of Detector::health_score's logistic unless configured otherwise, scoring a phi of
+/// 0 at ~0.9997 with the default threshold.
+pub const DEFAULT_HEALTH_STEEPNESS: f64 = 1.;
+
+/// Phi added by each heartbeat reported lost with Detector::report_missed_heartbeats, i.e. each
+/// one makes the node an order of magnitude more suspect in base 10.
+pub const MISSED_HEARTBEAT_PHI: f64 = 1.;
+
+/// Cap of Detector::phi_safe without a max_phi. Above the largest finite phi in either base, as
+/// `1 - F(t)` rounds to 0 long before phi could get there.
+pub const SAFE_MAX_PHI: f64 = 100.;
+
+/// Resolution intervals are stored at. The raw intervals exposed by Detector::interval_history and
+/// Detector::with_intervals are in this unit; everything derived from them, such as the mean and
+/// std, phi and the configured durations, stays in milliseconds.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum IntervalUnit {
+    /// Whole milliseconds, enough for heartbeats tens of milliseconds apart or more.
+    #[default]
+    Milliseconds,
+    /// Whole microseconds, for sub-millisecond heartbeats which milliseconds flatten to 0 or 1.
+    Microseconds,
+}
+
+impl IntervalUnit {
+    /// Units per millisecond.
+    fn per_ms(self) -> u64 {
+        match self {
+            IntervalUnit::Milliseconds => 1,
+            IntervalUnit::Microseconds => 1000,
         }
+    }
 
+    /// delta in whole units, saturating.
+    fn of(self, delta: TimeDelta) -> u64 {
+        let units = match self {
+            IntervalUnit::Milliseconds => delta.num_milliseconds(),
+            IntervalUnit::Microseconds => delta.num_microseconds().unwrap_or(i64::MAX),
+        };
+        units.max(0) as u64
+    }
 
-        if self.n - 1 == self.window_length {
-            self.arrival_intervals.remove(0);
-            self.n -= 1;
+    /// TimeDelta of units.
+    fn delta(self, units: u64) -> TimeDelta {
+        let units = units.min(i64::MAX as u64) as i64;
+        match self {
+            IntervalUnit::Milliseconds => TimeDelta::milliseconds(units),
+            IntervalUnit::Microseconds => TimeDelta::microseconds(units),
         }
-        if self.n != 0 {
-            let arrival_interval = arrived_at.sub(self.last_arrived_at).num_milliseconds() as u64;
-            self.arrival_intervals.push(arrival_interval);
+    }
+
+    /// Intervals are whole units, so a perfectly regular window is only known to within one unit;
+    /// flooring the std there keeps phi finite and increasing past the mean.
+    fn resolution(self) -> TimeDelta {
+        self.delta(1)
+    }
+
+    /// Column suffix for values in this unit.
+    fn suffix(self) -> &'static str {
+        match self {
+            IntervalUnit::Milliseconds => "ms",
+            IntervalUnit::Microseconds => "us",
         }
-        self.last_arrived_at = arrived_at;
-        self.n += 1;
     }
 }
 
-/// PhiCore trait for mean and variance calculation
-#[async_trait]
-trait PhiCore {
-    /// Calculate mean with existing stats.
-    async fn mean_with_stats<'a>(&self, stats: Arc<RwLockReadGuard<'a, Statistics>>) -> Result<f64, Box<dyn Error>>;
+/// How the acceptable pause enters the phi math. For the normal CDF the first two are equivalent,
+/// `F(t - pause; mean) == F(t; mean + pause)`, so phi is the same in both modes. They differ in
+/// the quantities around it: subtracting can feed a negative elapsed time into the CDF right
+/// after a heartbeat, whereas extending keeps the elapsed time as observed and reports the
+/// shifted mean, which is what the pause means for any logic relative to the mean.
+///
+/// Both shift the whole distribution, so the pause lowers phi for early elapsed times as well.
+/// LateOnly instead treats the pause as extra slack once a heartbeat is overdue: up to the mean
+/// phi is the same as with no pause, and only later elapsed times get the pause.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum PauseMode {
+    /// Subtract the pause from the elapsed time since the last heartbeat.
+    #[default]
+    SubtractFromElapsed,
+    /// Add the pause to the learned mean.
+    ExtendMean,
+    /// Leave elapsed times up to the mean alone; from the mean, phi holds at its value there for
+    /// the length of the pause and then continues as with SubtractFromElapsed.
+    LateOnly,
+}
 
-    /// Calculate variance and mean with existing stats.
-    async fn variance_and_mean(&self) -> Result<(f64, f64), Box<dyn Error>>;
+/// What happens to a heartbeat arriving sooner than the min interval after the previous one, e.g.
+/// a duplicate from batched delivery. See DetectorBuilder::with_min_interval.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum MinIntervalPolicy {
+    /// Ignore the heartbeat, the previous one stays the last arrival.
+    #[default]
+    Drop,
+    /// Fold the heartbeat into the previous one: it becomes the last arrival, so the next
+    /// interval is measured from it, but no interval is recorded for it.
+    Coalesce,
 }
 
-/// PhiInteraction trait for Detector
-#[async_trait]
-pub trait PhiInteraction {
-    /// Insertion of heartbeat arrival time.
-    async fn insert(&self, arrived_at: DateTime<Local>) -> Result<(), Box<dyn Error>>;
+/// Weight of a prior in samples: it dominates the first few intervals and fades as the window
+/// fills up.
+const PRIOR_WEIGHT: f64 = 10.;
 
-    /// Trait for phi for implementing struct
-    async fn phi(&self, t: DateTime<Local>) -> Result<f64, Box<dyn Error>>;
+/// Expected interval distribution blended into the learned one, see DetectorBuilder::with_prior,
+/// or replacing it, see DetectorBuilder::with_sla.
+#[derive(Clone, Copy, Debug)]
+struct Prior {
+    mean: f64,
+    variance: f64,
+}
 
-    /// Last arrival time of heartbeat
-    async fn last_arrived_at(&self) -> Result<DateTime<Local>, Box<dyn Error>>;
+/// What the elapsed time in phi is measured against.
+///
+/// Measured from the last arrival, the standard formulation, phi starts climbing right after a
+/// heartbeat and is already `log(2)` (0.3 in log10) when the next one is exactly on time at the
+/// mean. Measured against the next expected arrival at last arrival + mean, phi is 0 until then
+/// and only climbs once the heartbeat is overdue: only the upper half of the distribution counts,
+/// so `1 - F(t)` is doubled and phi is the standard one less `log(2)`, clamped at 0. Past the
+/// mean the two differ by that constant, so thresholds barely move for any practical value.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum PhiReference {
+    /// Elapsed time since the last heartbeat.
+    #[default]
+    LastArrival,
+    /// Overdue time past the last heartbeat plus the mean interval.
+    NextExpected,
 }
 
-/// Implementation of PhiCore for Detector
-#[async_trait]
-impl PhiCore for Detector {
-    async fn mean_with_stats<'a>(&self, stats: Arc<RwLockReadGuard<'a, Statistics>>) -> Result<f64, Box<dyn Error>> {
-        let mut mean: f64 = 0.;
-        let len = &stats.arrival_intervals.len();
-        for v in &stats.arrival_intervals {
-            mean += *v as f64 / *len as f64;
+/// Logarithm used in `phi = -log(1 - F(t))`. The original paper and Akka both use log10; some
+/// implementations use the natural logarithm instead, whose phi is ln(10) ≈ 2.3 times larger.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum PhiBase {
+    #[default]
+    Log10,
+    Ln,
+}
+
+impl PhiBase {
+    /// -log(p) in this base.
+    fn neg_log(self, p: f64) -> f64 {
+        match self {
+            PhiBase::Log10 => -log10(p),
+            PhiBase::Ln => -log(p),
         }
-        Ok(mean)
     }
 
-    async fn variance_and_mean(&self) -> Result<(f64, f64), Box<dyn Error>> {
-        let mut variance: f64 = 0.;
-        let stats = Arc::new(self.statistics.read().await);
-        let mu = self.mean_with_stats(Arc::clone(&stats)).await?;
-        let len = &stats.arrival_intervals.len();
-        for v in &stats.arrival_intervals {
-            let val = ((*v as f64 - mu) * (*v as f64 - mu)) / *len as f64;
-            variance += val;
+    /// Probability p with phi = -log(p) in this base.
+    fn probability(self, phi: f64) -> f64 {
+        match self {
+            PhiBase::Log10 => 10f64.powf(-phi),
+            PhiBase::Ln => exp(-phi),
         }
-        Ok((variance, mu))
     }
 }
 
-/// Cumulative distribution function for normal distribution
-fn normal_cdf(t: f64, mu: f64, sigma: f64) -> f64 {
-    if sigma == 0. {
-        return if t == mu {
-            1.
-        } else {
-            0.
-        };
+/// Builder for Detector instances needing more than a window_length and acceptable pause.
+#[derive(Clone, Debug)]
+pub struct DetectorBuilder {
+    window_length: u32,
+    acceptable_pause: TimeDelta,
+    ewma_alpha: Option<f64>,
+    network_offset: TimeDelta,
+    auto_window: Option<AutoWindow>,
+    interval_store: Option<Box<dyn IntervalStore>>,
+    config: Config,
+}
+
+/// Consistent snapshot of a Detector, see Detector::status.
+#[derive(Clone, Copy, Debug)]
+pub struct DetectorStatus {
+    pub phi: f64,
+    /// Mean interval in milliseconds.
+    pub mean: f64,
+    /// Standard deviation of the intervals in milliseconds, floored at the min std deviation.
+    pub std: f64,
+    /// Number of intervals in window.
+    pub sample_count: usize,
+    pub last_arrived_at: DateTime<Local>,
+    /// Most recent interval in whole milliseconds, None before the second heartbeat.
+    pub last_interval: Option<u64>,
+}
+
+/// Distribution phi is computed from, see Detector::stats_snapshot.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct StatsSnapshot {
+    /// Mean interval in milliseconds.
+    pub mean: f64,
+    /// Variance of the intervals in milliseconds squared, floored at the min std deviation.
+    pub variance: f64,
+}
+
+/// Coarse interpretation of phi for dashboards, see Detector::severity.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    /// Phi below the warning threshold.
+    Healthy,
+    /// Phi from the warning threshold up to and including the critical threshold.
+    Warning,
+    /// Phi above the critical threshold.
+    Critical,
+}
+
+/// A change in the heartbeat rate, see DetectorBuilder::with_rate_change_detection.
+#[derive(Clone, Copy, Debug)]
+pub struct RateChange {
+    /// Mean interval in milliseconds of the window before the change.
+    pub window_mean: f64,
+    /// Mean interval in milliseconds of the intervals since the change.
+    pub recent_mean: f64,
+    /// Arrival of the heartbeat at which the change was detected.
+    pub detected_at: DateTime<Local>,
+    /// Whether the window was reset to the intervals since the change.
+    pub reset: bool,
+}
+
+/// Verdict of is_suspected, see StateTransition.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NodeState {
+    Up,
+    Suspected,
+}
+
+/// A change in is_suspected's verdict, published to Detector::state_events.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct StateTransition {
+    pub from: NodeState,
+    pub to: NodeState,
+    /// Phi at which the verdict changed.
+    pub phi: f64,
+    /// Time passed to the is_suspected call that observed the change.
+    pub timestamp: DateTime<Local>,
+}
+
+/// Transitions a Detector::state_events receiver can fall behind by before it misses some.
+pub const STATE_EVENTS_CAPACITY: usize = 64;
+
+/// Phi at a point in time along with the inputs it was computed from, see Detector::phi_detailed.
+#[derive(Clone, Copy, Debug)]
+pub struct PhiReport {
+    pub phi: f64,
+    /// Mean interval in milliseconds.
+    pub mean: f64,
+    /// Variance of the intervals in milliseconds squared, floored at the min std deviation.
+    pub variance: f64,
+    /// Standard deviation of the intervals in milliseconds, floored at the min std deviation.
+    pub std: f64,
+    /// Milliseconds since the last heartbeat, before the acceptable pause is applied.
+    pub elapsed_ms: f64,
+    /// Number of intervals in window.
+    pub sample_count: usize,
+}
+
+/// Errors returned by Detector.
+#[derive(Clone, Debug, PartialEq)]
+pub enum PhiError {
+    /// Too few heartbeats or samples for the requested computation, e.g. no heartbeat yet.
+    NotEnoughSamples,
+    /// An epoch timestamp outside the range DateTime can represent.
+    InvalidTimestamp(i64),
+    /// A phi the detector never reaches, not positive or above max_phi.
+    UnreachablePhi(f64),
+    /// Parameters the detector can't be built from.
+    InvalidConfig(String),
+    /// Bytes that aren't a snapshot written by Detector::to_bytes.
+    CorruptSnapshot(String),
+    /// A line of a replayed CSV that isn't an RFC 3339 timestamp, by 1-based line number.
+    MalformedCsv(usize, String),
+    /// A request rejected by PhiHealthLayer as its node is suspected.
+    NodeSuspected,
+    /// A SyncDetector whose lock a panic poisoned, see SyncDetector::clear_poison.
+    LockPoisoned,
+    /// An interval in milliseconds carrying the last arrival past the range DateTime represents.
+    IntervalOutOfRange(u64),
+}
+
+impl fmt::Display for PhiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PhiError::NotEnoughSamples => write!(f, "not enough samples"),
+            PhiError::InvalidTimestamp(ms) => write!(f, "invalid epoch timestamp: {}ms", ms),
+            PhiError::UnreachablePhi(phi) => write!(f, "phi {} is never reached", phi),
+            PhiError::InvalidConfig(reason) => write!(f, "invalid config: {}", reason),
+            PhiError::CorruptSnapshot(reason) => write!(f, "corrupt snapshot: {}", reason),
+            PhiError::MalformedCsv(line, reason) => write!(f, "malformed CSV at line {}: {}", line, reason),
+            PhiError::NodeSuspected => write!(f, "node is suspected"),
+            PhiError::LockPoisoned => write!(f, "statistics lock poisoned by a panic"),
+            PhiError::IntervalOutOfRange(ms) => write!(f, "interval of {}ms is out of range", ms),
+        }
     }
+}
 
-    let z = (t - mu) / sigma;
-    0.5 + 0.5 * (erf(z))
+impl Error for PhiError {}
+
+/// Number of expected_interval intervals needed to cover history, rounded up and at least 1.
+fn window_length_for(expected_interval: TimeDelta, history: TimeDelta) -> u32 {
+    assert!(expected_interval > TimeDelta::zero(), "expected_interval must be positive");
+    let interval = expected_interval.num_microseconds().unwrap_or(i64::MAX) as u128;
+    let history = history.num_microseconds().unwrap_or(i64::MAX).max(0) as u128;
+    ((history + interval - 1) / interval).clamp(1, u32::MAX as u128) as u32
 }
 
-/// Implementation of PhiInteraction for Detector
-#[async_trait]
-impl PhiInteraction for Detector {
-    async fn insert(&self, arrived_at: DateTime<Local>) -> Result<(), Box<dyn Error>> {
-        let mut stats = self.statistics.write().await;
-        stats.insert(arrived_at);
-        Ok(())
+fn from_epoch_millis(ms: i64) -> Result<DateTime<Local>, PhiError> {
+    DateTime::from_timestamp_millis(ms)
+        .map(|t| t.with_timezone(&Local))
+        .ok_or(PhiError::InvalidTimestamp(ms))
+}
+
+/// Number of samples buffered for a subscriber before the sampling task waits on it.
+const SUBSCRIBER_BUFFER: usize = 16;
+
+/// A single phi reading pushed to subscribers.
+#[derive(Clone, Copy, Debug)]
+pub struct PhiSample {
+    pub phi: f64,
+    pub timestamp: DateTime<Local>,
+}
+
+impl Detector {
+    /// New Detector instance with window_length. Recommended window_length is < 10000, panics if
+    /// it is 0.
+    pub fn new(window_length: u32) -> Self {
+        Self::builder(window_length).build()
     }
 
-    async fn phi(&self, t: DateTime<Local>) -> Result<f64, Box<dyn Error>> {
-        let (sigma_sq, mu) = self.variance_and_mean().await?;
-        let sigma = sigma_sq.sqrt();
-        let last_arrived_at = self.last_arrived_at().await?;
-        let time_diff = t.sub(last_arrived_at).sub(self.acceptable_pause);
-        let ft = normal_cdf(time_diff.num_milliseconds() as f64, mu, sigma);
-        let phi = -log10(1. - ft);
-        Ok(phi)
+    /// New Detector instance with acceptable heartbeat pause duration.
+    pub fn with_acceptable_pause(window_length: u32, acceptable_pause: TimeDelta) -> Self {
+        Self::builder(window_length).with_acceptable_pause(acceptable_pause).build()
     }
 
-    async fn last_arrived_at(&self) -> Result<DateTime<Local>, Box<dyn Error>> {
-        Ok(self.statistics.read().await.last_arrived_at)
+    /// with_acceptable_pause for a std::time::Duration pause. Fails with PhiError::InvalidConfig
+    /// for a pause beyond what TimeDelta can represent, ~292 million years, rather than
+    /// truncating it.
+    pub fn with_acceptable_pause_std(window_length: u32, acceptable_pause: Duration) -> Result<Self, Box<dyn Error>> {
+        let acceptable_pause = TimeDelta::from_std(acceptable_pause).map_err(|_| {
+            PhiError::InvalidConfig(format!("acceptable pause {:?} is out of range", acceptable_pause))
+        })?;
+        Ok(Self::with_acceptable_pause(window_length, acceptable_pause))
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use std::ops::Add;
-    use chrono::{Duration, Local, TimeDelta};
-    use tokio::sync::RwLock;
-    use crate::{Detector, PhiCore, PhiInteraction, Statistics};
+    /// New Detector instance monitoring an SLA of heartbeats every mean_ms ± std_ms rather than
+    /// learning the distribution, see DetectorBuilder::with_sla.
+    pub fn sla(mean_ms: f64, std_ms: f64) -> Self {
+        Self::builder(1).with_sla(mean_ms, std_ms).build()
+    }
 
-    #[tokio::test]
-    async fn test_variant_mean_and_variance_combo_calculation() {
-        let mut stats = Statistics::new(10);
-        let mut i = 0;
-        let mut curr_time = Local::now();
-        &stats.insert(curr_time.clone());
-        let expect_vals = [1630, 4421, 1514, 216, 231, 931, 4182, 102, 104, 241, 5132];
-        while i < expect_vals.len() {
-            curr_time = curr_time.add(Duration::milliseconds(expect_vals[i]));
-            let arrived_at = curr_time;
-            &stats.insert(arrived_at);
-            i += 1;
-        }
+    /// New Detector instance feeding exponentially weighted mean and variance into phi,
+    /// see Statistics::with_ewma for picking alpha.
+    pub fn with_ewma(window_length: u32, alpha: f64) -> Self {
+        Self::builder(window_length).with_ewma(alpha).build()
+    }
+
+    /// New Detector instance keeping history worth of heartbeats arriving every expected_interval,
+    /// e.g. 60s of history at a 1s interval is a window_length of 60. The window_length is rounded
+    /// up so at least history is kept, and is at least 1. Panics if expected_interval isn't
+    /// positive.
+    pub fn with_history_duration(expected_interval: TimeDelta, history: TimeDelta) -> Self {
+        Self::new(window_length_for(expected_interval, history))
+    }
+
+    /// New Detector instance whose window is pre-seeded with intervals, see
+    /// Statistics::from_intervals.
+    pub fn from_intervals(
+        window_length: u32,
+        intervals: Vec<u64>,
+        last_arrived_at: DateTime<Local>,
+    ) -> Result<Self, Box<dyn Error>> {
+        let statistics = Statistics::from_intervals(window_length, intervals, last_arrived_at)?;
+        Ok(Self::from_parts(statistics, TimeDelta::zero(), Config::default()))
+    }
+
+    /// Compact binary snapshot of the statistics, a few bytes of header plus 8 bytes per interval
+    /// in window. The options the detector was built with aren't part of it. See the snapshot
+    /// module for the format, which starts with a version byte so it can evolve.
+    pub async fn to_bytes(&self) -> Vec<u8> {
+        self.statistics.read().await.to_bytes()
+    }
+
+    /// Consume the detector, returning its statistics, e.g. to archive them on teardown; the
+    /// owning counterpart of to_bytes. Heartbeats still queued in batching mode are applied
+    /// first.
+    pub fn into_statistics(mut self) -> Statistics {
+        let mut stats = std::mem::replace(self.statistics.get_mut(), Statistics::new(1));
+        self.drain_batch(&mut stats);
+        stats
+    }
+
+    /// New Detector instance restoring a snapshot from to_bytes, with default options and no
+    /// acceptable pause. Fails with PhiError::CorruptSnapshot if bytes aren't a valid snapshot.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Box<dyn Error>> {
+        let statistics = Statistics::from_bytes(bytes)?;
+        Ok(Self::from_parts(statistics, TimeDelta::zero(), Config::default()))
+    }
+
+    /// DetectorBuilder for a Detector with window_length.
+    pub fn builder(window_length: u32) -> DetectorBuilder {
+        DetectorBuilder::new(window_length)
+    }
+
+    fn from_parts(statistics: Statistics, acceptable_pause: TimeDelta, config: Config) -> Self {
+        let batch = config.insert_batching.then(Batch::new);
         let detector = Detector {
-            statistics: RwLock::new(stats),
-            acceptable_pause: TimeDelta::milliseconds(0),
+            window_length: AtomicU32::new(statistics.window_length),
+            statistics: RwLock::new(statistics),
+            acceptable_pause: AtomicI64::new(pause_micros(acceptable_pause)),
+            cache: Cache::default(),
+            config,
+            override_state: AtomicU8::new(OVERRIDE_NONE),
+            grace_until: AtomicI64::new(NO_GRACE),
+            batch,
+            one_way_delay: AtomicI64::new(NO_DELAY),
+            suspected: AtomicBool::new(false),
+            heartbeat: Notify::new(),
+            samples: AtomicUsize::new(0),
+            paused: AtomicBool::new(false),
+            missed: AtomicU32::new(0),
+            transitions: broadcast::channel(STATE_EVENTS_CAPACITY).0,
         };
-        let (mut variance, mut mean) = detector.variance_and_mean().await.unwrap();
-        mean = (mean * 100.0).round() * 0.01;
-        variance = (variance * 100.0).round() * 0.01;
-        assert_eq!(1707.4, mean);
-        assert_eq!(3755791.64, variance);
+        if let Some(grace) = detector.config.startup_grace {
+            let until = detector.config.clock.now() + grace;
+            detector.grace_until.store(until.timestamp_nanos_opt().unwrap_or(i64::MAX), Ordering::Relaxed);
+        }
+        if let Some(stats) = runtime::try_read(&detector.statistics) {
+            detector.refresh(&stats);
+        }
+        detector
+    }
 
-        let mut suspicion_level: Vec<f64> = vec![];
-        for i in 1..10 {
-            curr_time = curr_time.add(Duration::milliseconds(250));
-            suspicion_level.push(detector.phi(curr_time).await.unwrap())
+    /// Label set with DetectorBuilder::with_label.
+    pub fn label(&self) -> Option<&str> {
+        self.config.label.as_deref()
+    }
+
+    /// Acceptable heartbeat pause currently applied in phi.
+    pub fn acceptable_pause(&self) -> TimeDelta {
+        TimeDelta::microseconds(self.acceptable_pause.load(Ordering::Relaxed))
+    }
+
+    /// Pause phi currently applies: the acceptable pause, its adaptive counterpart or their sum
+    /// per DetectorBuilder::with_pause_model, from the distribution as of the last heartbeat.
+    pub fn effective_pause(&self) -> TimeDelta {
+        let variance = self.cache.load().map_or(0., |(variance, _, _)| variance);
+        TimeDelta::microseconds((self.pause_ms(variance) * 1000.).round() as i64)
+    }
+
+    /// Update the acceptable heartbeat pause. Takes effect for subsequent phi calls.
+    pub fn set_acceptable_pause(&self, acceptable_pause: TimeDelta) {
+        self.acceptable_pause.store(pause_micros(acceptable_pause), Ordering::Relaxed);
+    }
+
+    /// Mark the node as down, e.g. during a planned shutdown. Until the next heartbeat or
+    /// clear_override, phi is infinite (max_phi if capped) and is_suspected is true whatever
+    /// the computed phi. Takes precedence over the statistics, but not over force_up, which
+    /// replaces it.
+    pub fn force_down(&self) {
+        self.override_state.store(OVERRIDE_DOWN, Ordering::Relaxed);
+    }
+
+    /// Mark the node as up: until the next heartbeat or clear_override, phi is 0 and
+    /// is_suspected is false whatever the computed phi. Replaces a force_down.
+    pub fn force_up(&self) {
+        self.override_state.store(OVERRIDE_UP, Ordering::Relaxed);
+    }
+
+    /// Drop a force_down or force_up, going back to the computed phi.
+    pub fn clear_override(&self) {
+        self.override_state.store(OVERRIDE_NONE, Ordering::Relaxed);
+    }
+
+    /// Stop learning, e.g. during planned maintenance, so the gap doesn't end up in the window.
+    /// Until resume, heartbeats only move last_arrived_at without recording an interval and phi
+    /// is 0, while the intervals learned so far are kept.
+    pub fn pause(&self) {
+        self.paused.store(true, Ordering::Relaxed);
+    }
+
+    /// Undo pause. The next interval is measured from now on the detector's clock rather than the
+    /// last heartbeat before or during the pause.
+    pub async fn resume(&self) {
+        let mut stats = self.statistics.write().await;
+        if !self.paused.swap(false, Ordering::Relaxed) {
+            return;
         }
-        println!("suspicion -> {:?}", suspicion_level);
-        for i in 1..suspicion_level.len() {
-            assert!(suspicion_level[i] > suspicion_level[i - 1]);
+        let now = self.config.clock.now();
+        if stats.last_heartbeat().is_some_and(|last_arrived_at| last_arrived_at < now) {
+            stats.last_arrived_at = now;
+            self.refresh(&stats);
         }
     }
 
-    #[tokio::test]
-    async fn test_constant_phi_with_constant_pings_calculation() {
-        let stats = Statistics::new(10);
-        let detector = Detector {
-            statistics: RwLock::new(stats),
-            acceptable_pause: TimeDelta::milliseconds(0),
+    /// Whether the detector is paused, see pause.
+    pub fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::Relaxed)
+    }
+
+    /// Whether phi at t exceeds the configured threshold. With hysteresis the verdict instead
+    /// only flips to suspected above the up threshold and back below the down threshold, see
+    /// DetectorBuilder::with_hysteresis.
+    pub async fn is_suspected(&self, t: DateTime<Local>) -> Result<bool, Box<dyn Error>> {
+        let phi = self.phi(t).await?;
+        let (was_suspected, suspected) = self.verdict(phi);
+        if was_suspected != suspected {
+            let state = |suspected| if suspected { NodeState::Suspected } else { NodeState::Up };
+            let transition = StateTransition { from: state(was_suspected), to: state(suspected), phi, timestamp: t };
+            // no receiver isn't an error, the transition just goes unobserved
+            let _ = self.transitions.send(transition);
+        }
+        Ok(suspected)
+    }
+
+    /// Previous and new verdict for phi, updating the state held between the hysteresis
+    /// thresholds.
+    fn verdict(&self, phi: f64) -> (bool, bool) {
+        let suspected = match self.config.hysteresis {
+            Some((_, down)) if self.suspected.load(Ordering::Relaxed) => phi >= down,
+            Some((up, _)) => phi > up,
+            None => phi > self.config.threshold,
         };
-        let mut i = 0;
-        let mut curr_time = Local::now();
-        while i <= 100 {
-            let arrived_at = curr_time;
-            &detector.insert(arrived_at).await;
-            curr_time = curr_time.add(Duration::milliseconds(10));
-            i += 10;
+        (self.suspected.swap(suspected, Ordering::Relaxed), suspected)
+    }
+
+    /// Subscribe to changes in is_suspected's verdict, Up to Suspected and back. The verdict is
+    /// only evaluated by is_suspected, including through the registry's suspected_nodes, so a
+    /// transition is published by the call observing it, stamped with the time it was passed:
+    /// nothing is published while nobody asks. Every receiver gets every transition from its
+    /// subscription on; one lagging by more than STATE_EVENTS_CAPACITY misses the oldest, see
+    /// broadcast::error::RecvError::Lagged.
+    pub fn state_events(&self) -> broadcast::Receiver<StateTransition> {
+        self.transitions.subscribe()
+    }
+
+    /// Severity of phi at t: Healthy below the warning threshold, Critical above the critical
+    /// one and Warning in between, both thresholds included. Defaults to DEFAULT_WARNING_PHI and
+    /// DEFAULT_THRESHOLD, see DetectorBuilder::with_severity_thresholds.
+    pub async fn severity(&self, t: DateTime<Local>) -> Result<Severity, Box<dyn Error>> {
+        let phi = self.phi(t).await?;
+        Ok(if phi > self.config.critical_phi {
+            Severity::Critical
+        } else if phi >= self.config.warning_phi {
+            Severity::Warning
+        } else {
+            Severity::Healthy
+        })
+    }
+
+    /// Health of the node at t from 1, certainly up, to 0, certainly down, for weighing nodes
+    /// rather than cutting them off at the threshold. Phi goes through the logistic
+    /// `1 / (1 + e^(k * (phi - threshold)))`, centered on the threshold from with_threshold, where
+    /// the score is 0.5, with k from DetectorBuilder::with_health_steepness. In base 10 each unit
+    /// of phi is a tenfold drop in the odds of a heartbeat still arriving, so k = ln(10) ~ 2.3
+    /// moves the score's odds by the same factor, while the default of 1 is gentler. An infinite
+    /// phi scores 0. Hysteresis doesn't apply.
+    pub async fn health_score(&self, t: DateTime<Local>) -> Result<f64, Box<dyn Error>> {
+        let phi = self.phi(t).await?;
+        Ok(1. / (1. + exp(self.config.health_steepness * (phi - self.config.threshold))))
+    }
+
+    /// Insert a heartbeat stamped sent by the sender and received locally at received. The
+    /// window learns the cadence from send-to-send intervals, while received - sent is tracked
+    /// separately as the one-way delay, see one_way_delay, and phi measures the elapsed time from
+    /// the receipt of the last heartbeat.
+    ///
+    /// With receive times alone every interval carries the difference between two network
+    /// delays, so queueing jitter on the path widens the distribution and a single slow delivery
+    /// looks like a late heartbeat. Send times only carry the sender's own scheduling jitter, and
+    /// a constant skew between the two clocks cancels out of send-to-send intervals; it shows up
+    /// in the one-way delay instead, which is why that may be negative. Don't mix this with
+    /// insert on one detector: last_arrived_at and the window are in the sender's time. Never
+    /// batched, see DetectorBuilder::with_insert_batching.
+    pub async fn insert_with_send_time(
+        &self,
+        sent: DateTime<Local>,
+        received: DateTime<Local>,
+    ) -> Result<(), Box<dyn Error>> {
+        let mut stats = self.statistics.write().await;
+        let last_arrived_at = stats.last_heartbeat();
+        let recorded = self.record_heartbeat(&mut stats, sent);
+        // a heartbeat neither recorded nor moving the last arrival was dropped, e.g. as a short
+        // interval or a duplicate of the last one, and leaves the previous delay in place
+        if recorded.is_some() || stats.last_heartbeat() != last_arrived_at {
+            let delay = (received - sent).num_microseconds().unwrap_or(i64::MAX);
+            self.one_way_delay.store(delay, Ordering::Relaxed);
         }
-        let (mut variance, mut mean) = detector.variance_and_mean().await.unwrap();
-        mean = (mean * 100.0).round() * 0.01;
-        variance = (variance * 100.0).round() * 0.01;
-        assert_eq!(10., mean);
-        assert_eq!(0., variance);
-        curr_time = curr_time.add(Duration::milliseconds(10));
-        assert_eq!(0., detector.phi(curr_time).await.unwrap());
+        self.heartbeat_inserted(&stats, recorded);
+        Ok(())
+    }
+
+    /// received - sent of the last heartbeat inserted with insert_with_send_time, the network
+    /// delay plus the skew between the sender's clock and the local one. None if no heartbeat came
+    /// with a send time.
+    pub fn one_way_delay(&self) -> Option<TimeDelta> {
+        match self.one_way_delay.load(Ordering::Relaxed) {
+            NO_DELAY => None,
+            delay => Some(TimeDelta::microseconds(delay)),
+        }
+    }
+
+    /// Insert a heartbeat that arrived at ms milliseconds since the Unix epoch.
+    pub async fn insert_epoch_millis(&self, ms: i64) -> Result<Option<u64>, Box<dyn Error>> {
+        self.insert(from_epoch_millis(ms)?).await
+    }
+
+    /// Phi at ms milliseconds since the Unix epoch.
+    pub async fn phi_at_epoch_millis(&self, ms: i64) -> Result<f64, Box<dyn Error>> {
+        self.phi(from_epoch_millis(ms)?).await
+    }
+
+    /// Insert a heartbeat interval measured elsewhere, see Statistics::insert_interval. Like a
+    /// heartbeat it only moves last_arrived_at, without being recorded, while paused or in SLA
+    /// mode. Fails with PhiError::IntervalOutOfRange if the interval would carry the last arrival
+    /// past the range DateTime represents.
+    pub async fn insert_interval(&self, interval_ms: u64) -> Result<(), Box<dyn Error>> {
+        let mut stats = self.statistics.write().await;
+        stats.seq += 1;
+        let recorded = match stats.last_heartbeat() {
+            _ if self.is_paused() || self.config.sla.is_some() => {
+                stats.last_arrived_at = stats.arrival_after(interval_ms).ok_or(PhiError::IntervalOutOfRange(interval_ms))?;
+                stats.n = stats.n.max(1);
+                None
+            }
+            Some(_) if interval_ms < self.config.min_interval_ms => {
+                let arrived_at = stats.arrival_after(interval_ms).ok_or(PhiError::IntervalOutOfRange(interval_ms))?;
+                self.short_interval(&mut stats, arrived_at);
+                None
+            }
+            _ => {
+                stats.record_external_interval(interval_ms)?;
+                let recorded = stats.arrival_intervals.last();
+                self.detect_rate_change(&mut stats, recorded);
+                recorded
+            }
+        };
+        self.heartbeat_inserted(&stats, recorded);
+        Ok(())
+    }
+
+    /// Record a heartbeat with the write lock held, subject to the min interval policy. Returns
+    /// None for the first heartbeat, ignored backwards timestamps and short intervals.
+    fn record_heartbeat(&self, stats: &mut Statistics, arrived_at: DateTime<Local>) -> Option<u64> {
+        stats.seq += 1;
+        if self.is_paused() || self.config.sla.is_some() {
+            if stats.last_heartbeat().map_or(true, |last_arrived_at| arrived_at >= last_arrived_at) {
+                stats.last_arrived_at = arrived_at;
+                stats.n = stats.n.max(1);
+            }
+            return None;
+        }
+        match stats.last_heartbeat() {
+            Some(last_arrived_at) if arrived_at >= last_arrived_at
+                && ((arrived_at - last_arrived_at).num_milliseconds() as u64) < self.config.min_interval_ms => {
+                self.short_interval(stats, arrived_at);
+                None
+            }
+            _ => {
+                let recorded = stats.record_heartbeat(arrived_at);
+                self.detect_rate_change(stats, recorded);
+                recorded
+            }
+        }
+    }
+
+    /// Count interval towards a rate change, with the write lock held, reporting the change and
+    /// resetting the window if configured once enough consecutive intervals diverge.
+    fn detect_rate_change(&self, stats: &mut Statistics, interval: Option<u64>) {
+        let (Some(detection), Some(interval)) = (self.config.rate_change, interval) else {
+            return;
+        };
+        let streak = stats.rate_change_streak as usize + 1;
+        // the window mean leaves out the streak so far, which would otherwise drag it along
+        let older = stats.arrival_intervals.len().saturating_sub(streak);
+        if older == 0 {
+            stats.rate_change_streak = 0;
+            return;
+        }
+        let window_mean = stats.arrival_intervals.iter().take(older).sum::<u64>() as f64 / older as f64;
+        let interval = interval as f64;
+        if interval <= window_mean * detection.factor && interval * detection.factor >= window_mean {
+            stats.rate_change_streak = 0;
+            return;
+        }
+        if streak < detection.samples as usize {
+            stats.rate_change_streak = streak as u32;
+            return;
+        }
+        stats.rate_change_streak = 0;
+        let recent_mean = stats.arrival_intervals.iter().skip(older).sum::<u64>() as f64 / streak as f64;
+        if detection.reset {
+            stats.restart_window(streak);
+        }
+        if let Some(on_rate_change) = &self.config.on_rate_change {
+            let per_ms = stats.unit.per_ms() as f64;
+            (on_rate_change.0)(RateChange {
+                window_mean: window_mean / per_ms,
+                recent_mean: recent_mean / per_ms,
+                detected_at: stats.last_arrived_at,
+                reset: detection.reset,
+            });
+        }
+    }
+
+    /// Apply the heartbeats queued in batching mode with the write lock held, oldest first, then
+    /// refresh the cache once for all of them.
+    fn drain_batch(&self, stats: &mut Statistics) {
+        let Some(batch) = &self.batch else {
+            return;
+        };
+        let mut queued = Vec::new();
+        {
+            let mut rx = batch.rx.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+            while let Ok(arrived_at) = rx.try_recv() {
+                queued.push(arrived_at);
+            }
+        }
+        if queued.is_empty() {
+            return;
+        }
+        // concurrent inserts may queue out of order, which would otherwise count as regressions
+        queued.sort_unstable();
+        for arrived_at in queued {
+            let recorded = self.record_heartbeat(stats, arrived_at);
+            if let (Some(interval), Some(on_insert)) = (recorded, &self.config.on_insert) {
+                (on_insert.0)(interval, stats.last_arrived_at);
+            }
+            #[cfg(feature = "tracing")]
+            tracing::debug!(label = self.label(), interval_ms = ?recorded, %arrived_at, "heartbeat inserted");
+        }
+        self.heartbeat_inserted(stats, None);
+    }
+
+    /// Apply the heartbeats queued in batching mode now, waiting for the write lock. A no-op
+    /// without batching.
+    pub async fn flush(&self) {
+        if self.batch.is_some() {
+            self.drain_batch(&mut *self.statistics.write().await);
+        }
+    }
+
+    /// Spawns a task calling flush every interval, bounding how long a queued heartbeat can go
+    /// unapplied in batching mode. The task stops once the detector is dropped. Must be called
+    /// within the runtime selected by the crate's runtime feature.
+    pub fn spawn_flush(self: &Arc<Self>, interval: Duration) {
+        let detector = Arc::downgrade(self);
+        runtime::spawn(async move {
+            loop {
+                runtime::sleep(interval).await;
+                match detector.upgrade() {
+                    Some(detector) => detector.flush().await,
+                    None => break,
+                }
+            }
+        });
+    }
+
+    /// Bookkeeping after a heartbeat, with the write lock held: refresh the cache, end any
+    /// override or startup grace and hand the recorded interval, if any, to the insert callback.
+    fn heartbeat_inserted(&self, stats: &Statistics, recorded: Option<u64>) {
+        self.refresh(stats);
+        // Err only if there's nothing left to halve
+        let _ = self.missed.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |missed| {
+            (missed > 0).then_some(missed / 2)
+        });
+        self.clear_override();
+        self.grace_until.store(NO_GRACE, Ordering::Relaxed);
+        self.heartbeat.notify_waiters();
+        if let (Some(interval), Some(on_insert)) = (recorded, &self.config.on_insert) {
+            (on_insert.0)(interval, stats.last_arrived_at);
+        }
+    }
+
+    /// Write the intervals in window as CSV for offline analysis, oldest first, under a
+    /// `index,arrived_at,interval_ms` header: the interval's position in window, the RFC 3339
+    /// arrival time of the heartbeat closing it and the interval itself, in microseconds under
+    /// an `interval_us` column with IntervalUnit::Microseconds. The window is copied under a
+    /// single read lock, which is released before anything is written.
+    pub async fn export_intervals_csv(&self, mut writer: impl io::Write) -> io::Result<()> {
+        let (unit, history) = {
+            let stats = self.statistics.read().await;
+            let history: Vec<_> = stats.arrival_times.iter().copied().zip(stats.arrival_intervals.iter()).collect();
+            (stats.unit, history)
+        };
+        writeln!(writer, "index,arrived_at,interval_{}", unit.suffix())?;
+        for (index, (arrived_at, interval)) in history.into_iter().enumerate() {
+            writeln!(writer, "{},{},{}", index, arrived_at.to_rfc3339(), interval)?;
+        }
+        writer.flush()
+    }
+
+    /// Insert the heartbeats recorded in reader, one RFC 3339 timestamp per line in arrival order,
+    /// for offline analysis and tuning. Blank lines are skipped. The whole input is parsed
+    /// before anything is inserted, so a line that isn't a timestamp fails with
+    /// PhiError::MalformedCsv naming it and leaves the detector untouched.
+    pub async fn replay_csv(&self, reader: impl io::Read) -> Result<(), Box<dyn Error>> {
+        let mut heartbeats = Vec::new();
+        for (index, line) in io::BufRead::lines(io::BufReader::new(reader)).enumerate() {
+            let line = line?;
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            let arrived_at = DateTime::parse_from_rfc3339(line)
+                .map_err(|e| PhiError::MalformedCsv(index + 1, format!("{:?} isn't an RFC 3339 timestamp: {}", line, e)))?;
+            heartbeats.push(arrived_at.with_timezone(&Local));
+        }
+        for arrived_at in heartbeats {
+            self.insert(arrived_at).await?;
+        }
+        Ok(())
+    }
+
+    /// Intervals in window along with the arrival time of the heartbeat closing each, oldest first.
+    pub async fn interval_history(&self) -> Vec<(DateTime<Local>, u64)> {
+        let stats = self.statistics.read().await;
+        stats.arrival_times.iter().copied().zip(stats.arrival_intervals.iter()).collect()
+    }
+
+    /// Run f over the intervals in window, oldest first, with the read lock held. Avoids the
+    /// allocation of interval_history for the default store and any other IntervalStore whose
+    /// as_slice is Some; other stores are copied into a buffer first. Keep f short as inserts
+    /// wait on it.
+    pub async fn with_intervals<R>(&self, f: impl FnOnce(&[u64]) -> R) -> R {
+        let stats = self.statistics.read().await;
+        match stats.arrival_intervals.as_slice() {
+            Some(intervals) => f(intervals),
+            None => f(&stats.arrival_intervals.iter().collect::<Vec<_>>()),
+        }
+    }
+
+    /// Number of intervals the window holds once full, as of the last write for an auto-sized
+    /// window. Doesn't take the lock.
+    pub fn window_length(&self) -> u32 {
+        self.window_length.load(Ordering::Relaxed)
+    }
+
+    /// Smallest interval in window in whole ms, None if empty.
+    pub async fn min_interval_ms(&self) -> Option<u64> {
+        let stats = self.statistics.read().await;
+        stats.arrival_intervals.iter().min().map(|v| v / stats.unit.per_ms())
+    }
+
+    /// Largest interval in window in whole ms, None if empty.
+    pub async fn max_interval_ms(&self) -> Option<u64> {
+        let stats = self.statistics.read().await;
+        stats.arrival_intervals.iter().max().map(|v| v / stats.unit.per_ms())
+    }
+
+    /// Merge other's statistics into this detector, see Statistics::merge.
+    pub async fn merge(&self, other: &Detector) -> Result<(), Box<dyn Error>> {
+        if std::ptr::eq(self, other) {
+            return Ok(());
+        }
+        // snapshot other first so the two locks are never held together
+        let other_stats = other.statistics.read().await.clone();
+        if other_stats.unit != self.config.interval_unit {
+            return Err(PhiError::InvalidConfig("can't merge detectors with different interval units".into()).into());
+        }
+        let mut stats = self.statistics.write().await;
+        stats.merge(&other_stats);
+        self.refresh(&stats);
+        Ok(())
+    }
+
+    /// The CDF `F(t)` phi is derived from, `phi = -log(1 - F(t))`, for comparing the intermediate
+    /// with other implementations such as Akka's or Cassandra's: `1 - failure_probability`. With
+    /// the defaults it is the normal CDF taken at the elapsed time since the last heartbeat with
+    /// the acceptable pause applied, under the same mean and floored variance as phi. Overrides
+    /// and the startup grace don't apply. Fails with PhiError::NotEnoughSamples before the first
+    /// heartbeat.
+    pub async fn cdf_value(&self, t: DateTime<Local>) -> Result<f64, Box<dyn Error>> {
+        Ok(1. - self.failure_probability(t).await?)
+    }
+
+    /// Probability of the next heartbeat arriving later than t, i.e. `1 - F(t)`. This is the
+    /// value phi is derived from: `phi = -log10(p)`, so `p = 10^-phi`, or `e^-phi` with
+    /// PhiBase::Ln. It is computed from phi, so everything phi accounts for, e.g. a Student's t
+    /// tail, the idle decay, missed heartbeats and max_phi, applies too; overrides and the
+    /// startup grace don't. Always within [0, 1].
+    pub async fn failure_probability(&self, t: DateTime<Local>) -> Result<f64, Box<dyn Error>> {
+        let phi = self.phi_with_stats(&*self.statistics.read().await, t)?;
+        Ok(self.config.base.probability(phi).clamp(0., 1.))
+    }
+
+    /// Whether the probability that the node is down at t reached the probability threshold,
+    /// i.e. `failure_probability <= 1 - p`. Without one configured the threshold is the
+    /// probability equivalent to the phi threshold, `1 - 10^-threshold` in base 10.
+    pub async fn is_suspected_prob(&self, t: DateTime<Local>) -> Result<bool, Box<dyn Error>> {
+        let p = self.config.probability_threshold
+            .unwrap_or_else(|| 1. - self.config.base.probability(self.config.threshold));
+        Ok(1. - self.failure_probability(t).await? >= p)
+    }
+
+    /// Phi at t along with the distribution and elapsed time it was computed from, all read under
+    /// a single lock so the report explains the phi it carries. Phi follows force_down and
+    /// force_up like Detector::phi.
+    pub async fn phi_detailed(&self, t: DateTime<Local>) -> Result<PhiReport, Box<dyn Error>> {
+        let stats = self.statistics.read().await;
+        let last_arrived_at = self.warm_heartbeat(&stats)?;
+        let (variance, mean) = self.distribution(&stats);
+        let elapsed_ms = self.elapsed_ms_between(last_arrived_at, t);
+        Ok(PhiReport {
+            phi: self.overridden_phi(t).unwrap_or_else(|| self.phi_at(elapsed_ms, variance, mean)),
+            mean,
+            variance,
+            std: variance.sqrt(),
+            elapsed_ms,
+            sample_count: stats.arrival_intervals.len(),
+        })
+    }
+
+    /// Coefficient of variation `std / mean` of the intervals in window, a jitter measure on one
+    /// scale for nodes of any heartbeat rate: 0 for a metronome, around 0.1 for 10% jitter. It
+    /// is taken from the window alone, without the prior, outlier rejection or min std deviation
+    /// phi applies, and is 0 if every interval is 0. Fails with PhiError::NotEnoughSamples
+    /// before the first interval.
+    pub async fn coefficient_of_variation(&self) -> Result<f64, Box<dyn Error>> {
+        let stats = self.statistics.read().await;
+        if stats.arrival_intervals.is_empty() {
+            return Err(PhiError::NotEnoughSamples.into());
+        }
+        let (variance, mean) = stats.variance_and_mean();
+        Ok(if mean > 0. { variance.max(0.).sqrt() / mean } else { 0. })
+    }
+
+    /// Apply the min interval policy to a heartbeat at arrived_at, too soon after the last one.
+    fn short_interval(&self, stats: &mut Statistics, arrived_at: DateTime<Local>) {
+        if self.config.min_interval_policy == MinIntervalPolicy::Coalesce {
+            stats.last_arrived_at = arrived_at;
+        }
+    }
+
+    /// Mean and variance phi is currently computed from, prior, outlier rejection and the min std
+    /// deviation included. Pair with phi_with_snapshot to evaluate many elapsed times against one
+    /// distribution without going through the lock each time.
+    pub async fn stats_snapshot(&self) -> StatsSnapshot {
+        let (variance, mean) = self.distribution(&*self.statistics.read().await);
+        StatsSnapshot { mean, variance }
+    }
+
+    /// Phi elapsed_ms after a heartbeat under snapshot, with the acceptable pause and max_phi
+    /// applied as in phi. Overrides and the startup grace don't apply, there's no point in time.
+    pub fn phi_with_snapshot(&self, snapshot: &StatsSnapshot, elapsed_ms: f64) -> f64 {
+        self.phi_at(elapsed_ms, snapshot.variance, snapshot.mean)
+    }
+
+    /// Phi at t as in phi, but always finite and non-negative, so it can be compared and
+    /// serialized as is. The edge cases map to
+    ///
+    /// - before the first heartbeat, or the min samples: 0, nothing is suspected yet.
+    /// - t before the last heartbeat: the elapsed time is clamped to 0.
+    /// - a window whose intervals are all equal: the variance is floored at the min std
+    ///   deviation, as in phi.
+    /// - a gap long enough for `1 - F(t)` to round to 0, or force_down: max_phi if configured,
+    ///   otherwise SAFE_MAX_PHI.
+    /// - any other NaN: the cap as well, a result that can't be trusted is treated as suspect.
+    pub fn phi_safe(&self, t: DateTime<Local>) -> f64 {
+        let cap = self.config.max_phi.filter(|max_phi| max_phi.is_finite() && *max_phi >= 0.).unwrap_or(SAFE_MAX_PHI);
+        let phi = match (self.overridden_phi(t), self.warm_cache()) {
+            (Some(phi), _) => phi,
+            (None, Ok((variance, mean, last_arrived_at))) => {
+                self.phi_at(self.elapsed_ms_between(last_arrived_at, t).max(0.), variance, mean)
+            }
+            (None, Err(_)) => 0.,
+        };
+        if phi.is_nan() {
+            cap
+        } else {
+            phi.clamp(0., cap)
+        }
+    }
+
+    /// Cached distribution and last arrival once the detector is warm, see
+    /// DetectorBuilder::with_min_samples.
+    fn warm_cache(&self) -> Result<(f64, f64, DateTime<Local>), PhiError> {
+        if self.samples.load(Ordering::Relaxed) < self.config.min_samples {
+            return Err(PhiError::NotEnoughSamples);
+        }
+        self.cache.load().ok_or(PhiError::NotEnoughSamples)
+    }
+
+    /// Report count heartbeats the transport knows were lost, e.g. from a gap in sequence
+    /// numbers, to raise phi right away instead of waiting for the elapsed time to catch up.
+    /// Each outstanding miss adds MISSED_HEARTBEAT_PHI to phi, before max_phi. Reports add up,
+    /// and every heartbeat inserted afterwards halves the outstanding count, rounding down, so a
+    /// single miss is forgiven by the next heartbeat and a burst of 8 by the fourth.
+    pub fn report_missed_heartbeats(&self, count: u32) {
+        let _ = self.missed.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |missed| {
+            Some(missed.saturating_add(count))
+        });
+    }
+
+    /// Whether the statistics hold a heartbeat, inserted or restored, read without taking the lock.
+    /// Unlike last_arrived_at this can't be mistaken for a heartbeat at construction time. In
+    /// batching mode a heartbeat only counts once its batch is applied.
+    pub fn has_heartbeat(&self) -> bool {
+        self.cache.load().is_some()
+    }
+
+    /// Whether phi is available: a heartbeat arrived and the window holds the min samples.
+    pub fn is_warm(&self) -> bool {
+        self.warm_cache().is_ok()
+    }
+
+    /// Resolves once the detector is warm, see is_warm. Woken by each heartbeat rather than
+    /// polling.
+    pub async fn await_warm(&self) {
+        loop {
+            // registered before checking so a heartbeat in between isn't missed
+            let heartbeat = self.heartbeat.notified();
+            tokio::pin!(heartbeat);
+            heartbeat.as_mut().enable();
+            if self.is_warm() {
+                return;
+            }
+            heartbeat.await;
+        }
+    }
+
+    /// Phi at t under a distribution fitted elsewhere, with mean and variance in milliseconds, in
+    /// place of the window's. The last arrival, acceptable pause, overrides, startup grace and
+    /// max_phi apply as in phi. Fails with PhiError::NotEnoughSamples before the first heartbeat.
+    pub fn phi_with_params(&self, t: DateTime<Local>, mean: f64, variance: f64) -> Result<f64, Box<dyn Error>> {
+        if let Some(phi) = self.overridden_phi(t) {
+            return Ok(phi);
+        }
+        let (_, _, last_arrived_at) = self.warm_cache()?;
+        Ok(self.phi_at(self.elapsed_ms_between(last_arrived_at, t), variance, mean))
+    }
+
+    /// Phi at t set by force_down or force_up or suppressed by the startup grace, None if
+    /// neither applies.
+    fn overridden_phi(&self, t: DateTime<Local>) -> Option<f64> {
+        if self.is_paused() {
+            return Some(0.);
+        }
+        match self.override_state.load(Ordering::Relaxed) {
+            OVERRIDE_DOWN => return Some(self.config.max_phi.unwrap_or(f64::INFINITY)),
+            OVERRIDE_UP => return Some(0.),
+            _ => {}
+        }
+        let grace_until = self.grace_until.load(Ordering::Relaxed);
+        if grace_until != NO_GRACE && t.timestamp_nanos_opt().unwrap_or(i64::MAX) < grace_until {
+            return Some(0.);
+        }
+        None
+    }
+
+    /// Time after the last heartbeat at which phi reaches target_phi if no heartbeat arrives,
+    /// including the acceptable pause. Zero if phi is already at target_phi right after a
+    /// heartbeat. Reflects the statistics only, ignoring force_down, force_up and the startup
+    /// grace. Fails with PhiError::UnreachablePhi for a target that's not positive or above
+    /// max_phi.
+    pub async fn time_to_phi(&self, target_phi: f64) -> Result<TimeDelta, Box<dyn Error>> {
+        let stats = self.statistics.read().await;
+        stats.last_heartbeat().ok_or(PhiError::NotEnoughSamples)?;
+        Ok(self.time_to_phi_with_stats(&stats, target_phi)?)
+    }
+
+    /// Resolves once phi reaches threshold, i.e. no heartbeat arrived by the effective_deadline
+    /// for it. Sleeps on the runtime's timer until the predicted deadline and re-arms on each
+    /// heartbeat, so nothing polls phi in between. Like time_to_phi it reflects the statistics
+    /// only, ignoring overrides and the startup grace, and compares the deadline with the
+    /// configured clock. Before the first heartbeat it waits for one; for a threshold phi never
+    /// reaches, see time_to_phi, it never resolves.
+    pub async fn watch(&self, threshold: f64) {
+        loop {
+            // registered before the deadline is computed so a heartbeat in between isn't missed
+            let heartbeat = self.heartbeat.notified();
+            tokio::pin!(heartbeat);
+            heartbeat.as_mut().enable();
+            let Ok(deadline) = self.effective_deadline(threshold).await else {
+                heartbeat.await;
+                continue;
+            };
+            let now = self.config.clock.now();
+            let wait = match (deadline - now).to_std() {
+                Ok(wait) => wait,
+                // the deadline has passed, but phi only sees whole interval units of elapsed time
+                Err(_) => {
+                    let phi = self.phi_with_stats(&*self.statistics.read().await, now);
+                    if phi.is_ok_and(|phi| phi >= threshold) {
+                        return;
+                    }
+                    self.config.interval_unit.resolution().to_std().unwrap_or_default()
+                }
+            };
+            tokio::select! {
+                _ = runtime::sleep(wait) => {}
+                _ = heartbeat => {}
+            }
+        }
+    }
+
+    /// Wall-clock time at which the node becomes suspected at target_phi if no heartbeat
+    /// arrives: the last arrival plus time_to_phi, so the acceptable pause is accounted for.
+    pub async fn effective_deadline(&self, target_phi: f64) -> Result<DateTime<Local>, Box<dyn Error>> {
+        let stats = self.statistics.read().await;
+        let last_arrived_at = stats.last_heartbeat().ok_or(PhiError::NotEnoughSamples)?;
+        Ok(last_arrived_at + self.time_to_phi_with_stats(&stats, target_phi)?)
+    }
+
+    /// time_to_phi over a locked Statistics. Phi with the pause subtracted only depends on
+    /// `elapsed - pause`, so the search runs without the pause and adds it back, keeping the
+    /// pause's contribution exact.
+    fn time_to_phi_with_stats(&self, stats: &Statistics, target_phi: f64) -> Result<TimeDelta, PhiError> {
+        self.warm_heartbeat(stats)?;
+        let above_max = self.config.max_phi.is_some_and(|max_phi| target_phi > max_phi);
+        if target_phi.is_nan() || target_phi <= 0. || above_max {
+            return Err(PhiError::UnreachablePhi(target_phi));
+        }
+        let (variance, mean) = self.distribution(stats);
+        let pause_ms = self.pause_ms(variance);
+        let reached = |x: f64| self.uncapped_phi(x, variance, mean) >= target_phi;
+        let late_only = self.config.pause_mode == PauseMode::LateOnly;
+        // elapsed can't be negative, so the pause-free search starts at -pause
+        let mut lo = if late_only { 0. } else { -pause_ms };
+        if reached(lo) {
+            return Ok(TimeDelta::zero());
+        }
+        let mut hi = mean.max(1.);
+        let mut doublings = 0;
+        while !reached(hi) {
+            lo = hi;
+            hi *= 2.;
+            doublings += 1;
+            if doublings > 64 {
+                return Err(PhiError::UnreachablePhi(target_phi));
+            }
+        }
+        // to a microsecond, the resolution of the result
+        while hi - lo > 1e-3 {
+            let mid = lo + (hi - lo) / 2.;
+            if reached(mid) {
+                hi = mid;
+            } else {
+                lo = mid;
+            }
+        }
+        let micros = |ms: f64| TimeDelta::microseconds((ms * 1000.).ceil() as i64);
+        // with LateOnly a target reached by the mean comes before the pause applies
+        let pause_ms = if late_only && hi <= mean { 0. } else { pause_ms };
+        Ok(micros(hi) + micros(pause_ms))
+    }
+
+    /// Phi, mean, std, sample count, last arrival and last interval at t, all read under a single
+    /// lock so a concurrent heartbeat can't tear the view.
+    pub async fn status(&self, t: DateTime<Local>) -> Result<DetectorStatus, Box<dyn Error>> {
+        let stats = self.statistics.read().await;
+        let (variance, mean) = self.distribution(&stats);
+        Ok(DetectorStatus {
+            phi: self.phi_with_stats(&stats, t)?,
+            mean,
+            std: variance.sqrt(),
+            sample_count: stats.arrival_intervals.len(),
+            last_arrived_at: stats.last_heartbeat().ok_or(PhiError::NotEnoughSamples)?,
+            last_interval: stats.arrival_intervals.last().map(|v| v / stats.unit.per_ms()),
+        })
+    }
+
+    /// Every insert counts, insert_interval included, even one that doesn't record an interval,
+    /// e.g. an older heartbeat, so it correlates one to one with the sender's heartbeats in
+    /// external logs. The counter is per detector: neither a snapshot nor merge carries it over.
+    pub async fn current_seq(&self) -> u64 {
+        self.statistics.read().await.seq
+    }
+
+    /// Number of heartbeats dropped because they arrived with a timestamp earlier than the last one.
+    pub async fn clock_regression_count(&self) -> u64 {
+        self.statistics.read().await.clock_regressions
+    }
+
+    /// One-line summary of the detector at t for CLI tooling, e.g.
+    /// `UP (phi=0.30, mean=500ms, std=40ms, 1000 samples, last 480ms ago)`. The node is SUSPECT
+    /// once phi exceeds the configured threshold.
+    pub async fn describe(&self, t: DateTime<Local>) -> Result<String, Box<dyn Error>> {
+        let status = self.status(t).await?;
+        let state = if status.phi > self.config.threshold { "SUSPECT" } else { "UP" };
+        Ok(format!(
+            "{} (phi={:.2}, mean={:.0}ms, std={:.0}ms, {} samples, last {}ms ago)",
+            state,
+            status.phi,
+            status.mean,
+            status.std,
+            status.sample_count,
+            t.sub(status.last_arrived_at).num_milliseconds(),
+        ))
+    }
+
+    /// Slope of phi in phi per second, fitted by least squares over samples readings taken
+    /// interval apart and ending at t. Positive means suspicion is rising, i.e. the node is
+    /// degrading; the slope is NaN if phi saturated to infinity within the range. Fails with
+    /// PhiError::InvalidConfig if the readings would reach out of the range DateTime represents.
+    pub async fn phi_trend(&self, samples: usize, interval: TimeDelta, t: DateTime<Local>) -> Result<f64, Box<dyn Error>> {
+        if samples < 2 {
+            return Err(Box::new(PhiError::NotEnoughSamples));
+        }
+        let out_of_range = || PhiError::InvalidConfig(format!("{} readings {} apart reach out of range", samples, interval));
+        // checked up front so a huge samples fails before allocating
+        let span_ms = i64::try_from(samples - 1).ok().and_then(|steps| interval.num_milliseconds().checked_mul(steps));
+        span_ms.and_then(TimeDelta::try_milliseconds).and_then(|span| t.checked_sub_signed(span)).ok_or_else(out_of_range)?;
+        let mut points = Vec::with_capacity(samples);
+        let mut at = t;
+        for k in 0..samples {
+            if k > 0 {
+                at = at.checked_sub_signed(interval).ok_or_else(out_of_range)?;
+            }
+            let x = -(k as f64) * interval.num_milliseconds() as f64 / 1000.;
+            points.push((x, self.phi(at).await?));
+        }
+        let n = samples as f64;
+        let mean_x = points.iter().map(|(x, _)| x).sum::<f64>() / n;
+        let mean_y = points.iter().map(|(_, y)| y).sum::<f64>() / n;
+        let covariance: f64 = points.iter().map(|(x, y)| (x - mean_x) * (y - mean_y)).sum();
+        let variance: f64 = points.iter().map(|(x, _)| (x - mean_x).powi(2)).sum();
+        Ok(covariance / variance)
+    }
+
+    /// Phi extra after the last heartbeat, i.e. phi if the next heartbeat doesn't arrive for
+    /// extra. Like phi, this doesn't mutate state. An extra reaching past the range DateTime
+    /// represents is taken at the edge of that range, so phi is its value for an outage of
+    /// ~262k years, infinite or max_phi.
+    pub async fn phi_after(&self, extra: TimeDelta) -> Result<f64, Box<dyn Error>> {
+        let (_, _, last_arrived_at) = self.cache.load().ok_or(PhiError::NotEnoughSamples)?;
+        let t = last_arrived_at.checked_add_signed(extra).unwrap_or_else(|| {
+            // a day inside the range, so the local offset can't push it out
+            let edge = if extra > TimeDelta::zero() {
+                DateTime::<Utc>::MAX_UTC - TimeDelta::days(1)
+            } else {
+                DateTime::<Utc>::MIN_UTC + TimeDelta::days(1)
+            };
+            edge.with_timezone(&Local)
+        });
+        self.phi(t).await
+    }
+
+    /// Time from the last heartbeat to t, negative for t before it. Fails with
+    /// PhiError::NotEnoughSamples before the first heartbeat.
+    pub fn time_since_last(&self, t: DateTime<Local>) -> Result<TimeDelta, Box<dyn Error>> {
+        let (_, _, last_arrived_at) = self.cache.load().ok_or(PhiError::NotEnoughSamples)?;
+        Ok(t - last_arrived_at)
+    }
+
+    /// time_since_last in milliseconds, at the resolution of the interval unit as used by phi.
+    pub fn elapsed_ms(&self, t: DateTime<Local>) -> Result<f64, Box<dyn Error>> {
+        let (_, _, last_arrived_at) = self.cache.load().ok_or(PhiError::NotEnoughSamples)?;
+        Ok(self.elapsed_ms_between(last_arrived_at, t))
+    }
+
+    /// Phi at t as if the acceptable pause were zero, whether fixed or adaptive, for comparing the
+    /// strict suspicion with the lenient one from phi. Overrides, the startup grace and max_phi
+    /// apply as they do to phi. Fails with PhiError::NotEnoughSamples before the first heartbeat.
+    pub fn raw_phi(&self, t: DateTime<Local>) -> Result<f64, Box<dyn Error>> {
+        if let Some(phi) = self.overridden_phi(t) {
+            return Ok(phi);
+        }
+        let (variance, mean, last_arrived_at) = self.warm_cache()?;
+        Ok(self.raw_phi_at(self.elapsed_ms_between(last_arrived_at, t), variance, mean))
+    }
+
+    /// Phi at the clock's current time.
+    pub async fn phi_now(&self) -> Result<f64, Box<dyn Error>> {
+        self.phi(self.config.clock.now()).await
+    }
+
+    /// Insert a heartbeat arriving at the clock's current time.
+    pub async fn insert_now(&self) -> Result<Option<u64>, Box<dyn Error>> {
+        self.insert(self.config.clock.now()).await
+    }
+
+    /// Spawns a task sampling phi_now every interval and pushing the readings into the returned
+    /// channel. The task stops once the receiver is dropped. Must be called within the runtime
+    /// selected by the crate's runtime feature.
+    pub fn subscribe(self: &Arc<Self>, interval: Duration) -> mpsc::Receiver<PhiSample> {
+        let (tx, rx) = mpsc::channel(SUBSCRIBER_BUFFER);
+        let detector = Arc::clone(self);
+        runtime::spawn(async move {
+            loop {
+                let timestamp = detector.config.clock.now();
+                let phi = detector.phi(timestamp).await.ok();
+                if let Some(phi) = phi {
+                    if tx.send(PhiSample { phi, timestamp }).await.is_err() {
+                        break;
+                    }
+                }
+                tokio::select! {
+                    _ = runtime::sleep(interval) => {}
+                    _ = tx.closed() => break,
+                }
+            }
+        });
+        rx
+    }
+
+    /// Insert every heartbeat of stream as it arrives until the stream ends, returning the number
+    /// of heartbeats inserted. The reactive counterpart of subscribe: pair the two to feed the
+    /// detector and consume its phi without polling either side.
+    pub async fn drive_from_stream(
+        &self,
+        mut stream: impl Stream<Item = DateTime<Local>> + Unpin,
+    ) -> Result<u64, Box<dyn Error>> {
+        let mut inserted = 0;
+        while let Some(arrived_at) = stream.next().await {
+            self.insert(arrived_at).await?;
+            inserted += 1;
+        }
+        Ok(inserted)
+    }
+}
+
+/// Detector::new(DEFAULT_WINDOW_LENGTH): a window of 1000 intervals and no acceptable pause.
+impl Default for Detector {
+    fn default() -> Self {
+        Detector::new(DEFAULT_WINDOW_LENGTH)
+    }
+}
+
+/// Debug prints the learned distribution rather than the lock around it. It doesn't wait on the
+/// lock: while a writer holds it the statistics show as `<locked>`.
+impl fmt::Debug for Detector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut debug = f.debug_struct("Detector");
+        if let Some(label) = self.label() {
+            debug.field("label", &label);
+        }
+        match runtime::try_read(&self.statistics) {
+            Some(stats) => {
+                debug.field("window_length", &stats.window_length).field("samples", &stats.len());
+                if stats.is_empty() {
+                    debug.field("mean", &None::<f64>).field("std", &None::<f64>);
+                } else {
+                    let (variance, mean) = self.distribution(&stats);
+                    debug.field("mean", &Some(mean)).field("std", &Some(variance.sqrt()));
+                }
+            }
+            None => {
+                debug.field("statistics", &format_args!("<locked>"));
+            }
+        }
+        debug.field("acceptable_pause", &self.acceptable_pause()).finish()
+    }
+}
+
+/// Clone is a point-in-time snapshot: the statistics are copied under the read lock into a fresh
+/// lock, so inserts into either detector afterwards don't affect the other. Clone can't await the
+/// lock, so it spins until the lock is free: it must not run while a DetectorView, a with_intervals
+/// callback or any other read guard is held, as a writer queued behind that reader keeps the lock
+/// from the spin and, on the same task or a current_thread runtime, the reader from finishing.
+/// From async code prefer Detector::snapshot_clone.
+impl Clone for Detector {
+    fn clone(&self) -> Self {
+        let statistics = loop {
+            match runtime::try_read(&self.statistics) {
+                Some(stats) => break stats.clone(),
+                None => std::thread::yield_now(),
+            }
+        };
+        self.fork(statistics)
+    }
+}
+
+impl Detector {
+    /// Point-in-time copy of the detector like Clone, awaiting the read lock instead of spinning
+    /// on it, so it is safe to call while other tasks hold or wait for the lock.
+    pub async fn snapshot_clone(&self) -> Detector {
+        let statistics = self.statistics.read().await.clone();
+        self.fork(statistics)
+    }
+
+    /// Detector over statistics with this one's config and state.
+    fn fork(&self, statistics: Statistics) -> Detector {
+        let detector = Detector::from_parts(statistics, self.acceptable_pause(), self.config.clone());
+        detector.override_state.store(self.override_state.load(Ordering::Relaxed), Ordering::Relaxed);
+        detector.one_way_delay.store(self.one_way_delay.load(Ordering::Relaxed), Ordering::Relaxed);
+        detector.suspected.store(self.suspected.load(Ordering::Relaxed), Ordering::Relaxed);
+        detector.paused.store(self.paused.load(Ordering::Relaxed), Ordering::Relaxed);
+        detector.grace_until.store(self.grace_until.load(Ordering::Relaxed), Ordering::Relaxed);
+        detector.missed.store(self.missed.load(Ordering::Relaxed), Ordering::Relaxed);
+        detector
+    }
+}
+
+impl DetectorBuilder {
+    /// New DetectorBuilder with window_length, no acceptable pause and uncapped phi.
+    pub fn new(window_length: u32) -> Self {
+        DetectorBuilder {
+            window_length,
+            acceptable_pause: TimeDelta::milliseconds(0),
+            ewma_alpha: None,
+            network_offset: TimeDelta::zero(),
+            auto_window: None,
+            interval_store: None,
+            config: Config::default(),
+        }
+    }
+
+    /// Acceptable heartbeat pause subtracted from the elapsed time in phi.
+    pub fn with_acceptable_pause(mut self, acceptable_pause: TimeDelta) -> Self {
+        self.acceptable_pause = acceptable_pause;
+        self
+    }
+
+    /// Subtract offset from every interval computed by insert, clamped at 0, so the window
+    /// reflects the sender's cadence rather than a systematic delay added to each interval. Note a
+    /// delay that is the same for every heartbeat shifts all arrivals alike and already cancels
+    /// out of the intervals. Unlike the acceptable pause, which only shifts the elapsed time in
+    /// phi, this changes the learned distribution. Intervals given to insert_interval are taken
+    /// as measured and not offset.
+    pub fn with_network_offset(mut self, offset: TimeDelta) -> Self {
+        self.network_offset = offset;
+        self
+    }
+
+    /// Size the window automatically: it starts at window_length and doubles, up to
+    /// max_window_length, each time it fills up, until the variance of the full window differs
+    /// from that of the previous size by at most epsilon relative to it. The window then holds
+    /// its size, also once it reaches max_window_length without converging. A noisier node thus
+    /// gets a longer window, at the cost of the memory and the slower reaction a long window
+    /// brings; the cap bounds both.
+    pub fn with_auto_window(mut self, max_window_length: u32, epsilon: f64) -> Self {
+        self.auto_window = Some(AutoWindow {
+            max_window_length,
+            epsilon,
+            last_variance: None,
+            converged: false,
+        });
+        self
+    }
+
+    /// Exponentially weighted mean and variance, see Statistics::with_ewma.
+    pub fn with_ewma(mut self, alpha: f64) -> Self {
+        self.ewma_alpha = Some(alpha);
+        self
+    }
+
+    /// Keep the window's intervals in store instead of a Vec, see IntervalStore. Panics in build
+    /// if the store isn't empty.
+    pub fn with_interval_store(mut self, store: Box<dyn IntervalStore>) -> Self {
+        self.interval_store = Some(store);
+        self
+    }
+
+    /// Store intervals as u32, halving what the store takes but not the window: the arrival
+    /// times stay as they are, so an entry drops from about 24 to 20 bytes. See CompactIntervals
+    /// for the longest interval it represents.
+    pub fn with_compact_intervals(self) -> Self {
+        self.with_interval_store(Box::<CompactIntervals>::default())
+    }
+
+    /// Saturate phi at max_phi. Once `1 - F(t)` underflows to 0 phi is `+inf`, which e.g. can't
+    /// be serialized to JSON; with a cap the detector reports max_phi instead.
+    pub fn with_max_phi(mut self, max_phi: f64) -> Self {
+        self.config.max_phi = Some(max_phi);
+        self
+    }
+
+    /// Compute phi from a fixed mean and standard deviation in milliseconds, e.g. an SLA of
+    /// heartbeats every second ± 100ms, instead of learning them: phi is deterministic in the
+    /// time since the last heartbeat. Heartbeats then only move last_arrived_at without
+    /// recording an interval, so the window stays empty, and the prior, outlier rejection and
+    /// the other options shaping the learned distribution have no effect. The min std deviation
+    /// still floors std_ms.
+    pub fn with_sla(mut self, mean_ms: f64, std_ms: f64) -> Self {
+        self.config.sla = Some(Prior { mean: mean_ms, variance: std_ms * std_ms });
+        self
+    }
+
+    /// Seed the distribution with an expected mean and standard deviation in milliseconds. The
+    /// prior is pooled with the window as if it were PRIOR_WEIGHT (10) intervals, which keeps
+    /// phi stable while only a handful of real intervals exist and fades as more arrive.
+    pub fn with_prior(mut self, mean_ms: f64, std_ms: f64) -> Self {
+        self.config.prior = Some(Prior { mean: mean_ms, variance: std_ms * std_ms });
+        self
+    }
+
+    /// Lower bound for the standard deviation fed into phi, one interval unit (1ms by default) if
+    /// unset. Without a floor a window of identical intervals has zero variance and phi jumps
+    /// straight from 0 to infinity at the mean; the floor turns that into a steep but smooth
+    /// rise. Zero disables it.
+    pub fn with_min_std_deviation(mut self, min_std_deviation: TimeDelta) -> Self {
+        self.config.min_std_deviation = Some(min_std_deviation);
+        self
+    }
+
+    /// Phi above which the node is considered suspected, DEFAULT_THRESHOLD (8) by default.
+    pub fn with_threshold(mut self, threshold: f64) -> Self {
+        self.config.threshold = threshold;
+        self
+    }
+
+    /// Suspect the node once the probability that it is down, `F(t) = 1 - failure_probability`,
+    /// reaches p, e.g. 0.99 for "99% sure it's down". This is the phi threshold
+    /// `-log10(1 - p)` expressed without the log scale: 0.9 is phi 1, 0.99 is phi 2 and
+    /// 0.99999999 is the default phi threshold of 8. See Detector::is_suspected_prob.
+    pub fn with_probability_threshold(mut self, p: f64) -> Self {
+        self.config.probability_threshold = Some(p);
+        self
+    }
+
+    /// Widen the tolerance of a node idle for long: once the elapsed time, past the acceptable
+    /// pause, exceeds after times the mean interval, phi is computed with the variance
+    /// multiplied by factor until the next heartbeat. A node legitimately slow to heartbeat after
+    /// a long idle period then isn't flagged by a distribution learned while it was busy. phi
+    /// drops where the decay sets in, so it is only non-decreasing on either side of that point,
+    /// and time_to_phi and watch may find the crossing before it.
+    ///
+    /// The decay is measured from the last heartbeat, so a detector restored from saved
+    /// statistics whose last arrival is stale starts out decayed. The startup grace still takes
+    /// precedence while it lasts, reporting phi 0; once it ends the decayed variance applies
+    /// until real heartbeats have replaced the restored state.
+    pub fn with_idle_decay(mut self, factor: f64, after: f64) -> Self {
+        self.config.idle_decay = Some((factor, after));
+        self
+    }
+
+    /// Compute phi from a Student's t distribution with `sample_count - 1` degrees of freedom
+    /// while the window holds fewer than below_samples intervals, and from the normal
+    /// distribution from then on. The mean and std are estimated from the few intervals of a
+    /// warming window, and the t distribution's heavier tails account for that uncertainty: phi
+    /// climbs slower than the normal's overconfident tail would have it. The two converge as
+    /// the window fills: at 30 samples the t tail 3 stds out is still about twice the normal's,
+    /// but that only lowers phi by ~0.3, so around 30 is a sensible crossover. A window of a
+    /// single interval has no degree of freedom and uses the normal distribution either way.
+    pub fn with_student_t_tail(mut self, below_samples: usize) -> Self {
+        self.config.student_t_below = Some(below_samples);
+        self
+    }
+
+    /// Tag the detector with label, e.g. the monitored node's id, for detectors sharing logging
+    /// infrastructure: it is recorded as a `label` field on the tracing spans and events and shown
+    /// in the Debug output.
+    pub fn with_label(mut self, label: impl Into<String>) -> Self {
+        self.config.label = Some(label.into());
+        self
+    }
+
+    /// Measure phi's elapsed time against reference, see PhiReference.
+    pub fn with_phi_reference(mut self, reference: PhiReference) -> Self {
+        self.config.reference = reference;
+        self
+    }
+
+    /// Hold is_suspected's verdict between two thresholds so phi hovering around one doesn't flap
+    /// alerts: a node becomes suspected once phi exceeds up_threshold and is only up again once
+    /// phi drops below down_threshold, which should be lower. Replaces the threshold from
+    /// with_threshold for is_suspected and the registry's suspected_nodes. The state advances
+    /// with each is_suspected call, so verdicts depend on the times queried, and the calls are
+    /// expected to move forward in time.
+    pub fn with_hysteresis(mut self, up_threshold: f64, down_threshold: f64) -> Self {
+        self.config.hysteresis = Some((up_threshold, down_threshold));
+        self
+    }
+
+    /// Phi thresholds for Detector::severity: Warning from warning_phi and Critical above
+    /// critical_phi. They are independent of the suspicion threshold from with_threshold.
+    pub fn with_severity_thresholds(mut self, warning_phi: f64, critical_phi: f64) -> Self {
+        self.config.warning_phi = warning_phi;
+        self.config.critical_phi = critical_phi;
+        self
+    }
+
+    /// Steepness k of Detector::health_score's logistic, DEFAULT_HEALTH_STEEPNESS by default. The
+    /// larger k, the closer the score gets to the binary verdict of is_suspected; build_checked
+    /// rejects a k that isn't positive.
+    pub fn with_health_steepness(mut self, k: f64) -> Self {
+        self.config.health_steepness = k;
+        self
+    }
+
+    /// Scale the acceptable pause with the node's own jitter: phi uses `k * std` in place of the
+    /// fixed pause, so jittery nodes get more leeway than metronomic ones. The std is the one
+    /// fed into phi, floored at the min std deviation, so the pause is at least
+    /// `k * min_std_deviation`.
+    pub fn with_adaptive_pause(mut self, k: f64) -> Self {
+        self.config.adaptive_pause = Some(k);
+        self.config.pause_model_base = false;
+        self
+    }
+
+    /// Acceptable pause of `base + k * std`: a constant floor covering e.g. network latency, plus
+    /// the jitter-scaled leeway of with_adaptive_pause, which it replaces. The std is the one
+    /// fed into phi, and the sum enters the phi math wherever the fixed pause would, per the
+    /// PauseMode and in time_to_phi, so it grows and shrinks with the window. base is the
+    /// detector's acceptable pause: it replaces with_acceptable_pause and set_acceptable_pause
+    /// changes it later. See Detector::effective_pause for the current sum.
+    pub fn with_pause_model(mut self, base: TimeDelta, k: f64) -> Self {
+        self.acceptable_pause = base;
+        self.config.adaptive_pause = Some(k);
+        self.config.pause_model_base = true;
+        self
+    }
+
+    /// Exclude intervals more than `k` stds from the window mean when computing the mean and
+    /// variance fed into phi, so a single long pause such as a GC stall doesn't inflate the
+    /// variance for the whole window. Excluded intervals stay in the window and are reconsidered
+    /// on every heartbeat. This does not apply in EWMA mode.
+    ///
+    /// One outlier among n intervals is at most `sqrt(n - 1)` stds from the mean, so small
+    /// windows need a small `k` to catch one. Rejection can also mask real degradation: a node
+    /// whose intervals grow gradually keeps rejecting the slowest ones and looks healthier than it
+    /// is, until enough of them accumulate to shift the mean.
+    pub fn with_outlier_rejection(mut self, k: f64) -> Self {
+        self.config.outlier_rejection = Some(k);
+        self
+    }
+
+    /// Compute the mean and variance fed into phi from a trimmed window: a sorted copy of the
+    /// intervals without the fraction f of them at each end, e.g. 0.1 drops the lowest and
+    /// highest 10%. A simpler robust statistic than outlier rejection, which it replaces if
+    /// both are set, and one that still tracks gradual degradation once it shifts the bulk of
+    /// the window. The copy is sorted on every insert, `O(n log n)` for a window of n rather than
+    /// the `O(n)` of the plain mean, which matters for windows of many thousand intervals at
+    /// high heartbeat rates. This does not apply in EWMA mode.
+    pub fn with_trimmed_fraction(mut self, f: f64) -> Self {
+        self.config.trimmed_fraction = Some(f);
+        self
+    }
+
+    /// Compute phi from the median of the window and `1.4826 * MAD`, the median absolute
+    /// deviation from it scaled to estimate the std of normally distributed intervals, instead
+    /// of the mean and std. Both medians ignore up to half the window being outliers, where a
+    /// single stall already drags the mean and inflates the std; in turn the spread only reacts
+    /// once a change reaches half the window, and a sorted copy of the window is taken twice on
+    /// every insert, `O(n log n)` rather than `O(n)`. Takes precedence over the trimmed fraction
+    /// and outlier rejection, and does not apply in EWMA mode.
+    pub fn with_median_spread(mut self) -> Self {
+        self.config.median_spread = true;
+        self
+    }
+
+    /// Withhold phi until the window holds at least n intervals: phi and everything derived from
+    /// it, e.g. status, failure_probability and time_to_phi, fail with PhiError::NotEnoughSamples
+    /// before then, phi_safe reports 0, and is_warm and await_warm report it. A distribution
+    /// learned from a handful of intervals is mostly noise.
+    pub fn with_min_samples(mut self, n: usize) -> Self {
+        self.config.min_samples = n;
+        self
+    }
+
+    /// Report phi 0 for grace after construction, until the grace expires or a heartbeat
+    /// arrives. A detector restored from saved statistics is constructed fresh and so gets a
+    /// fresh grace: its last arrival may be long stale by the time it's queried, and without the
+    /// grace phi would spike before the node has had a chance to heartbeat again. A clone keeps
+    /// the original's grace instead, so both report the same phi. Overrides from force_down and
+    /// force_up take precedence.
+    pub fn with_startup_grace(mut self, grace: TimeDelta) -> Self {
+        self.config.startup_grace = Some(grace);
+        self
+    }
+
+    /// Clock consulted by phi_now, insert_now, subscribe, the startup grace and the construction
+    /// time in place of the system time.
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.config.clock = clock;
+        self
+    }
+
+    /// Handle heartbeats arriving less than min_ms after the previous one per the min interval
+    /// policy instead of recording their interval. By default every interval is recorded, so
+    /// duplicate timestamps record intervals of 0 which pull the mean towards 0 and the std
+    /// down; a min_ms of 1 keeps duplicates out of the window.
+    pub fn with_min_interval(mut self, min_ms: u64) -> Self {
+        self.config.min_interval_ms = min_ms;
+        self
+    }
+
+    /// How heartbeats under the min interval are handled, see MinIntervalPolicy.
+    pub fn with_min_interval_policy(mut self, policy: MinIntervalPolicy) -> Self {
+        self.config.min_interval_policy = policy;
+        self
+    }
+
+    /// Resolution intervals are stored at, see IntervalUnit.
+    pub fn with_interval_unit(mut self, unit: IntervalUnit) -> Self {
+        self.config.interval_unit = unit;
+        self
+    }
+
+    /// Call f with every interval recorded by an insert, in the interval unit, and the arrival
+    /// time of the heartbeat closing it, e.g. to forward intervals to a time-series database.
+    /// f runs while the write lock is held, so phi readers aren't affected but other inserts wait
+    /// on it: keep it fast and non-blocking, and hand slow work off to a channel. It isn't called
+    /// for the first heartbeat, ignored ones or intervals brought in by merge. A panic in f
+    /// unwinds out of insert after the interval is recorded; the runtime's locks don't poison,
+    /// so the detector stays usable. A SyncDetector's std lock does, see SyncDetector.
+    pub fn with_on_insert(mut self, f: impl Fn(u64, DateTime<Local>) + Send + Sync + 'static) -> Self {
+        self.config.on_insert = Some(OnInsert(Arc::new(f)));
+        self
+    }
+
+    /// How the acceptable pause is applied, see PauseMode.
+    pub fn with_pause_mode(mut self, pause_mode: PauseMode) -> Self {
+        self.config.pause_mode = pause_mode;
+        self
+    }
+
+    /// Logarithm base of phi, log10 by default.
+    pub fn with_phi_base(mut self, base: PhiBase) -> Self {
+        self.config.base = base;
+        self
+    }
+
+    /// Queue inserted heartbeats instead of each taking the write lock, for high heartbeat rates
+    /// where inserts contend on it. An insert pushes its heartbeat onto a queue without locking
+    /// and, if the write lock happens to be free, drains everything queued so far under it. A
+    /// heartbeat queued while the lock is held elsewhere is only reflected in phi, the
+    /// statistics and the insert callback once the next insert finds the lock free, or at the
+    /// next Detector::flush, which Detector::spawn_flush calls periodically. Heartbeats applied
+    /// together are sorted by arrival time first. insert_interval and insert_with_send_time aren't
+    /// batched.
+    pub fn with_insert_batching(mut self) -> Self {
+        self.config.insert_batching = true;
+        self
+    }
+
+    /// Detect a change in the heartbeat rate, such as a node reconfigured from a 1s to a 5s
+    /// interval, which would otherwise keep phi off until the old intervals leave the window. A
+    /// change is detected once samples consecutive intervals are over factor times the mean of
+    /// the intervals before them, or under that mean divided by factor, and is reported to the
+    /// callback from with_on_rate_change. With reset the window then restarts from those samples
+    /// intervals, so phi follows the new rate right away; without it only the report is made.
+    /// factor should be over 1 and samples at least 1.
+    pub fn with_rate_change_detection(mut self, factor: f64, samples: u32, reset: bool) -> Self {
+        self.config.rate_change = Some(RateChangeDetection { factor, samples, reset });
+        self
+    }
+
+    /// Call f with every rate change detected, see with_rate_change_detection. Like the insert
+    /// callback f runs while the write lock is held, so keep it fast.
+    pub fn with_on_rate_change(mut self, f: impl Fn(RateChange) + Send + Sync + 'static) -> Self {
+        self.config.on_rate_change = Some(OnRateChange(Arc::new(f)));
+        self
+    }
+
+    /// Build the configured Detector after checking for clearly pathological settings, failing
+    /// with a descriptive PhiError::InvalidConfig on the first one found: a zero window, an
+    /// EWMA alpha outside (0, 1], a negative acceptable pause, a min std deviation that isn't
+    /// positive, a threshold that isn't positive or a trimmed fraction outside [0, 0.5). With a
+    /// prior its mean stands in for the expected interval, and an acceptable pause over
+    /// MAX_PAUSE_INTERVALS times it or a min std deviation over it are rejected too. build
+    /// accepts all of these as they are.
+    pub fn build_checked(self) -> Result<Detector, Box<dyn Error>> {
+        self.validate()?;
+        Ok(self.build())
+    }
+
+    fn validate(&self) -> Result<(), PhiError> {
+        let invalid = |reason: String| Err(PhiError::InvalidConfig(reason));
+        if self.window_length == 0 {
+            return invalid("window_length must be at least 1".into());
+        }
+        if let Some(alpha) = self.ewma_alpha {
+            if !(alpha > 0. && alpha <= 1.) {
+                return invalid(format!("EWMA alpha must be within (0, 1], got {}", alpha));
+            }
+        }
+        if self.acceptable_pause < TimeDelta::zero() {
+            return invalid(format!("acceptable pause must not be negative, got {}", self.acceptable_pause));
+        }
+        if let Some(min_std) = self.config.min_std_deviation {
+            if min_std <= TimeDelta::zero() {
+                return invalid(format!("min std deviation must be positive, got {}", min_std));
+            }
+        }
+        if self.config.threshold.is_nan() || self.config.threshold <= 0. {
+            return invalid(format!("threshold must be positive, got {}", self.config.threshold));
+        }
+        if self.config.health_steepness.is_nan() || self.config.health_steepness <= 0. {
+            return invalid(format!("health steepness must be positive, got {}", self.config.health_steepness));
+        }
+        if let Some(f) = self.config.trimmed_fraction {
+            if !(0. ..0.5).contains(&f) {
+                return invalid(format!("trimmed fraction must be within [0, 0.5), got {}", f));
+            }
+        }
+        let Some(prior) = self.config.prior else {
+            return Ok(());
+        };
+        let pause_ms = pause_micros(self.acceptable_pause) as f64 / 1000.;
+        if pause_ms > MAX_PAUSE_INTERVALS * prior.mean {
+            return invalid(format!(
+                "acceptable pause of {}ms is over {} times the expected interval of {}ms",
+                pause_ms, MAX_PAUSE_INTERVALS, prior.mean
+            ));
+        }
+        if let Some(min_std) = self.config.min_std_deviation {
+            let min_std_ms = pause_micros(min_std) as f64 / 1000.;
+            if min_std_ms > prior.mean {
+                return invalid(format!(
+                    "min std deviation of {}ms is over the expected interval of {}ms",
+                    min_std_ms, prior.mean
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Build the configured Detector.
+    pub fn build(self) -> Detector {
+        let mut statistics = Statistics::with_clock(self.window_length, &*self.config.clock);
+        statistics.ewma = self.ewma_alpha.map(Ewma::new);
+        statistics.unit = self.config.interval_unit;
+        statistics.network_offset = self.network_offset;
+        statistics.auto_window = self.auto_window;
+        if let Some(store) = self.interval_store {
+            statistics.set_store(store);
+        }
+        Detector::from_parts(statistics, self.acceptable_pause, self.config)
+    }
+}
+
+impl Statistics {
+    /// New Statistics instance with window_length. Panics if window_length is 0, a window has to
+    /// hold at least one interval. A window_length of 1 is the minimal viable window: phi takes
+    /// the last interval as the mean, and its variance of 0 is floored at the min std deviation,
+    /// so phi climbs steeply once the elapsed time passes that interval. The construction time,
+    /// which intervals inserted before the first heartbeat count from, is read from the system
+    /// clock, see with_clock.
+    pub fn new(window_length: u32) -> Self {
+        Self::with_clock(window_length, &SystemClock)
+    }
+
+    /// New Statistics instance as new, reading the construction time from clock.
+    pub fn with_clock(window_length: u32, clock: &dyn Clock) -> Self {
+        assert!(window_length > 0, "window_length must be at least 1 to hold an interval");
+        Self {
+            arrival_intervals: Box::<Vec<u64>>::default(),
+            arrival_times: VecDeque::new(),
+            last_arrived_at: clock.now(),
+            window_length,
+            n: 0,
+            clock_regressions: 0,
+            ewma: None,
+            unit: IntervalUnit::default(),
+            network_offset: TimeDelta::zero(),
+            auto_window: None,
+            rate_change_streak: 0,
+            seq: 0,
+        }
+    }
+
+    /// New Statistics instance whose mean and variance are exponentially weighted with decay
+    /// alpha instead of uniform over the window. A sample k inserts old carries weight
+    /// `(1 - alpha)^k`, so the half-life in samples is `ln(0.5) / ln(1 - alpha)`, or conversely
+    /// `alpha = 1 - 0.5^(1 / half_life)`. For example alpha = 0.1 halves a sample's weight
+    /// after ~6.6 heartbeats. The window is still kept for everything else.
+    pub fn with_ewma(window_length: u32, alpha: f64) -> Self {
+        Self {
+            ewma: Some(Ewma::new(alpha)),
+            ..Self::new(window_length)
+        }
+    }
+
+    /// New Statistics instance keeping its intervals in store, see IntervalStore. Panics if
+    /// window_length is 0 or the store isn't empty.
+    pub fn with_store(window_length: u32, store: Box<dyn IntervalStore>) -> Self {
+        let mut stats = Self::new(window_length);
+        stats.set_store(store);
+        stats
+    }
+
+    fn set_store(&mut self, store: Box<dyn IntervalStore>) {
+        assert!(store.is_empty(), "an interval store must start out empty");
+        self.arrival_intervals = store;
+    }
+
+    /// New Statistics instance whose window holds intervals in milliseconds, oldest first, the last
+    /// one closed by the heartbeat at last_arrived_at. Fails with PhiError::InvalidConfig if the intervals don't
+    /// fit in window_length, and with PhiError::IntervalOutOfRange if they reach back past the
+    /// range DateTime represents.
+    pub fn from_intervals(
+        window_length: u32,
+        intervals: Vec<u64>,
+        last_arrived_at: DateTime<Local>,
+    ) -> Result<Self, Box<dyn Error>> {
+        if window_length == 0 || intervals.len() > window_length as usize {
+            let reason = format!("{} intervals don't fit a window of {}", intervals.len(), window_length);
+            return Err(PhiError::InvalidConfig(reason).into());
+        }
+        let mut arrival_times = VecDeque::with_capacity(intervals.len());
+        let mut arrived_at = last_arrived_at;
+        for interval in intervals.as_slice().iter().rev() {
+            arrival_times.push_front(arrived_at);
+            arrived_at = i64::try_from(*interval)
+                .ok()
+                .and_then(TimeDelta::try_milliseconds)
+                .and_then(|interval| arrived_at.checked_sub_signed(interval))
+                .ok_or(PhiError::IntervalOutOfRange(*interval))?;
+        }
+        Ok(Self {
+            n: intervals.len() as u32 + 1,
+            arrival_intervals: Box::new(intervals),
+            arrival_times,
+            last_arrived_at,
+            ..Self::new(window_length)
+        })
+    }
+
+    /// Insert heartbeat arrival time in window. A heartbeat older than the last one means the
+    /// time source went backwards: it is counted in clock_regressions and otherwise ignored.
+    pub fn insert(&mut self, arrived_at: DateTime<Local>) {
+        self.seq += 1;
+        self.record_heartbeat(arrived_at);
+    }
+
+    /// insert, returning the interval recorded for the heartbeat if any.
+    fn record_heartbeat(&mut self, arrived_at: DateTime<Local>) -> Option<u64> {
+
+        // insert first element
+        if self.n == 0 {
+            self.last_arrived_at = arrived_at;
+            self.n += 1;
+            return None;
+        }
+
+        if arrived_at < self.last_arrived_at {
+            self.clock_regressions += 1;
+            return None;
+        }
+
+        // of clamps at 0, so the offset never makes an interval negative
+        let arrival_interval = self.unit.of(arrived_at.sub(self.last_arrived_at) - self.network_offset);
+        self.record_interval(arrival_interval, arrived_at);
+        self.last_arrived_at = arrived_at;
+        Some(arrival_interval)
+    }
+
+    /// Insert a heartbeat interval measured elsewhere, advancing last_arrived_at by it. Before any
+    /// heartbeat the interval is counted from the construction time. An interval that would
+    /// carry last_arrived_at past the range DateTime represents is counted but not recorded, see
+    /// Detector::insert_interval for one that fails instead.
+    pub fn insert_interval(&mut self, interval_ms: u64) {
+        self.seq += 1;
+        let _ = self.record_external_interval(interval_ms);
+    }
+
+    /// Arrival interval_ms after the last one, None past the range DateTime represents.
+    fn arrival_after(&self, interval_ms: u64) -> Option<DateTime<Local>> {
+        i64::try_from(interval_ms)
+            .ok()
+            .and_then(TimeDelta::try_milliseconds)
+            .and_then(|interval| self.last_arrived_at.checked_add_signed(interval))
+    }
+
+    /// insert_interval without counting towards seq, failing on an interval out of range.
+    fn record_external_interval(&mut self, interval_ms: u64) -> Result<(), PhiError> {
+        let arrived_at = self.arrival_after(interval_ms).ok_or(PhiError::IntervalOutOfRange(interval_ms))?;
+        if self.n == 0 {
+            self.n = 1;
+        }
+        self.last_arrived_at = arrived_at;
+        self.record_interval(interval_ms.saturating_mul(self.unit.per_ms()), arrived_at);
+        Ok(())
+    }
+
+    /// Push an interval closed by the heartbeat at arrived_at into the window, evicting the oldest
+    /// one when full.
+    fn record_interval(&mut self, interval: u64, arrived_at: DateTime<Local>) {
+        if self.n - 1 == self.window_length {
+            self.arrival_intervals.evict_oldest();
+            self.arrival_times.pop_front();
+            self.n -= 1;
+        }
+        self.arrival_intervals.push(interval);
+        self.arrival_times.push_back(arrived_at);
+        if let Some(ewma) = &mut self.ewma {
+            ewma.update(interval);
+        }
+        self.n += 1;
+        if self.arrival_intervals.len() == self.window_length as usize {
+            self.grow_window();
+        }
+    }
+
+    /// Drop all but the last keep intervals from the window, rebuilding the EWMA from the rest.
+    fn restart_window(&mut self, keep: usize) {
+        let dropped = self.arrival_intervals.len().saturating_sub(keep);
+        for _ in 0..dropped {
+            self.arrival_intervals.evict_oldest();
+        }
+        self.arrival_times.drain(..dropped);
+        self.n -= dropped as u32;
+        self.rebuild_ewma();
+    }
+
+    /// Double an auto-sized window that just filled up, unless its variance moved by less than
+    /// epsilon since it last filled up or it reached its cap, in which case it holds from then on.
+    fn grow_window(&mut self) {
+        if !self.auto_window.is_some_and(|auto| !auto.converged) {
+            return;
+        }
+        let (variance, _) = self.variance_and_mean();
+        let Some(auto) = &mut self.auto_window else {
+            return;
+        };
+        let stable = auto.last_variance
+            .is_some_and(|last| (variance - last).abs() <= auto.epsilon * last.max(f64::MIN_POSITIVE));
+        if stable || self.window_length >= auto.max_window_length {
+            auto.converged = true;
+            return;
+        }
+        auto.last_variance = Some(variance);
+        self.window_length = self.window_length.saturating_mul(2).min(auto.max_window_length);
+    }
+
+    /// Merge another window's intervals into this one. Intervals of the window which saw the
+    /// older last heartbeat come first, so eviction keeps the most recent ones. The merged window
+    /// keeps this window's window_length regardless of other's, and last_arrived_at is the later
+    /// of the two.
+    ///
+    /// Panics if the two windows store intervals in different units.
+    pub fn merge(&mut self, other: &Statistics) {
+        assert_eq!(self.unit, other.unit, "can't merge windows with different interval units");
+        self.clock_regressions += other.clock_regressions;
+        if other.n == 0 {
+            return;
+        }
+        // merged into this window's store, whatever the other one is backed by
+        if self.n == 0 {
+            for interval in other.arrival_intervals.iter() {
+                self.arrival_intervals.push(interval);
+            }
+            self.arrival_times = other.arrival_times.clone();
+            self.last_arrived_at = other.last_arrived_at;
+        } else if other.last_arrived_at > self.last_arrived_at {
+            for interval in other.arrival_intervals.iter() {
+                self.arrival_intervals.push(interval);
+            }
+            self.arrival_times.extend(other.arrival_times.iter().copied());
+            self.last_arrived_at = other.last_arrived_at;
+        } else {
+            let own: Vec<u64> = std::iter::from_fn(|| self.arrival_intervals.evict_oldest()).collect();
+            for interval in other.arrival_intervals.iter().chain(own) {
+                self.arrival_intervals.push(interval);
+            }
+            let mut merged = other.arrival_times.clone();
+            merged.extend(self.arrival_times.drain(..));
+            self.arrival_times = merged;
+        }
+
+        let window_length = self.window_length as usize;
+        if self.arrival_intervals.len() > window_length {
+            let excess = self.arrival_intervals.len() - window_length;
+            for _ in 0..excess {
+                self.arrival_intervals.evict_oldest();
+            }
+            self.arrival_times.drain(..excess);
+        }
+        self.n = self.arrival_intervals.len() as u32 + 1;
+
+        // an EWMA can't be merged, rebuild it from the merged window
+        self.rebuild_ewma();
+    }
+
+    /// Recompute the EWMA, if any, from the intervals in window.
+    fn rebuild_ewma(&mut self) {
+        if let Some(ewma) = &mut self.ewma {
+            *ewma = Ewma::new(ewma.alpha);
+            for interval in self.arrival_intervals.iter() {
+                ewma.update(interval);
+            }
+        }
+    }
+
+    /// Number of intervals the window holds once full.
+    pub fn window_length(&self) -> u32 {
+        self.window_length
+    }
+
+    /// Number of intervals in window.
+    pub fn len(&self) -> usize {
+        self.arrival_intervals.len()
+    }
+
+    /// Whether the window holds no interval yet.
+    pub fn is_empty(&self) -> bool {
+        self.arrival_intervals.is_empty()
+    }
+
+    /// Arrival time of the last heartbeat, None before the first one.
+    pub fn last_arrived_at(&self) -> Option<DateTime<Local>> {
+        self.last_heartbeat()
+    }
+
+    /// Number of heartbeats inserted so far, see Detector::current_seq.
+    pub fn seq(&self) -> u64 {
+        self.seq
+    }
+
+    /// Intervals in window in the interval unit, oldest first.
+    pub fn intervals(&self) -> impl Iterator<Item = u64> + '_ {
+        self.arrival_intervals.iter()
+    }
+
+    /// Arrival time of the last heartbeat, None before the first one.
+    fn last_heartbeat(&self) -> Option<DateTime<Local>> {
+        if self.n == 0 {
+            None
+        } else {
+            Some(self.last_arrived_at)
+        }
+    }
+
+    /// Mean of the intervals in window.
+    fn mean(&self) -> f64 {
+        let sum: f64 = self.arrival_intervals.iter().map(|v| v as f64).sum();
+        sum / self.arrival_intervals.len() as f64
+    }
+
+    /// Variance and mean of the intervals in window, exponentially weighted in EWMA mode. The
+    /// window variance is computed in two passes over the deviations from the mean rather than as
+    /// `E[x^2] - mean^2`, which cancels catastrophically for large, near-constant intervals; the
+    /// EWMA is updated incrementally in Welford's style, which can't go negative either.
+    fn variance_and_mean(&self) -> (f64, f64) {
+        if let Some(ewma) = &self.ewma {
+            return (ewma.variance, ewma.mean);
+        }
+        let mut variance: f64 = 0.;
+        let mu = self.mean();
+        let len = self.arrival_intervals.len();
+        for v in self.arrival_intervals.iter() {
+            let val = ((v as f64 - mu) * (v as f64 - mu)) / len as f64;
+            variance += val;
+        }
+        (variance, mu)
+    }
+
+    /// Variance and mean of the intervals in window without the f lowest and f highest, falling
+    /// back to all of them in EWMA mode or if trimming would leave none.
+    fn variance_and_mean_trimmed(&self, f: f64) -> (f64, f64) {
+        if self.ewma.is_some() {
+            return self.variance_and_mean();
+        }
+        let mut sorted: Vec<u64> = self.arrival_intervals.iter().collect();
+        sorted.sort_unstable();
+        let trimmed = (sorted.len() as f64 * f) as usize;
+        if 2 * trimmed >= sorted.len() {
+            return self.variance_and_mean();
+        }
+        let kept = &sorted[trimmed..sorted.len() - trimmed];
+        let len = kept.len() as f64;
+        let mean = kept.iter().sum::<u64>() as f64 / len;
+        let variance = kept.iter().map(|v| (*v as f64 - mean) * (*v as f64 - mean)).sum::<f64>() / len;
+        (variance, mean)
+    }
+
+    /// Squared `1.4826 * MAD` and median of the intervals in window, falling back to the variance
+    /// and mean in EWMA mode or for an empty window.
+    fn variance_and_median_mad(&self) -> (f64, f64) {
+        if self.ewma.is_some() || self.arrival_intervals.is_empty() {
+            return self.variance_and_mean();
+        }
+        let median = |sorted: &[f64]| {
+            let mid = sorted.len() / 2;
+            if sorted.len() % 2 == 0 { (sorted[mid - 1] + sorted[mid]) / 2. } else { sorted[mid] }
+        };
+        let mut sorted: Vec<f64> = self.arrival_intervals.iter().map(|v| v as f64).collect();
+        sorted.sort_unstable_by(f64::total_cmp);
+        let center = median(&sorted);
+        let mut deviations: Vec<f64> = sorted.iter().map(|v| (v - center).abs()).collect();
+        deviations.sort_unstable_by(f64::total_cmp);
+        let std = 1.4826 * median(&deviations);
+        (std * std, center)
+    }
+
+    /// Variance and mean of the intervals in window within k stds of the window mean, falling
+    /// back to all of them in EWMA mode or if none would be kept.
+    fn variance_and_mean_within(&self, k: f64) -> (f64, f64) {
+        let (variance, mu) = self.variance_and_mean();
+        if self.ewma.is_some() {
+            return (variance, mu);
+        }
+        let bound = k * variance.sqrt();
+        let kept: Vec<f64> = self.arrival_intervals.iter()
+            .map(|v| v as f64)
+            .filter(|v| (v - mu).abs() <= bound)
+            .collect();
+        if kept.is_empty() || kept.len() == self.arrival_intervals.len() {
+            return (variance, mu);
+        }
+        let len = kept.len() as f64;
+        let mean = kept.iter().sum::<f64>() / len;
+        let variance = kept.iter().map(|v| (v - mean) * (v - mean)).sum::<f64>() / len;
+        (variance, mean)
+    }
+}
+
+/// PhiCore trait for CDF and phi calculation over a locked Statistics
+trait PhiCore {
+    /// Variance and mean fed into the CDF: stats' own without rejected outliers, pooled with the
+    /// prior if one is set, with the variance floored at min_std_deviation.
+    fn distribution(&self, stats: &Statistics) -> (f64, f64);
+
+    /// Variance and mean learned from stats in milliseconds, the prior included, before the min
+    /// std deviation.
+    fn learned_distribution(&self, stats: &Statistics) -> (f64, f64);
+
+    /// Elapsed milliseconds between last_arrived_at and t, less the one-way delay of heartbeats
+    /// inserted with their send time.
+    fn elapsed_ms_between(&self, last_arrived_at: DateTime<Local>, t: DateTime<Local>) -> f64;
+
+    /// Acceptable pause in milliseconds, adaptive to the std if configured.
+    fn pause_ms(&self, variance: f64) -> f64;
+
+    /// Elapsed milliseconds and mean with the acceptable pause applied per the pause mode.
+    fn apply_pause(&self, elapsed_ms: f64, variance: f64, mean: f64) -> (f64, f64);
+
+    /// Last heartbeat in stats, failing with PhiError::NotEnoughSamples before the first one or
+    /// the min samples.
+    fn warm_heartbeat(&self, stats: &Statistics) -> Result<DateTime<Local>, PhiError>;
+
+    /// Phi at t computed from stats.
+    fn phi_with_stats(&self, stats: &Statistics, t: DateTime<Local>) -> Result<f64, PhiError>;
+
+    /// Phi for elapsed_ms under variance and mean with the acceptable pause applied, in the
+    /// configured base and capped at max_phi.
+    fn phi_at(&self, elapsed_ms: f64, variance: f64, mean: f64) -> f64;
+
+    /// phi_at with no acceptable pause, whatever is configured.
+    fn raw_phi_at(&self, elapsed_ms: f64, variance: f64, mean: f64) -> f64;
+
+    /// Phi for elapsed_ms against the configured reference, in the configured base, with the idle
+    /// decay and missed heartbeats applied, after the acceptable pause and before max_phi.
+    fn uncapped_phi(&self, elapsed_ms: f64, variance: f64, mean: f64) -> f64;
+
+    /// Refresh the lock-free cache from stats, called with the write lock held.
+    fn refresh(&self, stats: &Statistics);
+}
+
+/// PhiInteraction trait for Detector
+#[async_trait]
+pub trait PhiInteraction {
+    /// Insertion of heartbeat arrival time, returning the interval it closed: None for the first
+    /// heartbeat and for heartbeats that weren't recorded, e.g. ones older than the last.
+    async fn insert(&self, arrived_at: DateTime<Local>) -> Result<Option<u64>, Box<dyn Error>>;
+
+    /// Trait for phi for implementing struct. t may be arbitrarily far in the future to ask
+    /// what phi would be if no heartbeat arrived until then; computing phi never mutates state.
+    async fn phi(&self, t: DateTime<Local>) -> Result<f64, Box<dyn Error>>;
+
+    /// Last arrival time of heartbeat
+    async fn last_arrived_at(&self) -> Result<DateTime<Local>, Box<dyn Error>>;
+}
+
+/// Implementation of PhiCore for Detector
+impl PhiCore for Detector {
+    fn distribution(&self, stats: &Statistics) -> (f64, f64) {
+        let (variance, mean) = match self.config.sla {
+            Some(sla) => (sla.variance, sla.mean),
+            None => self.learned_distribution(stats),
+        };
+        let min_std = self.config.min_std_deviation.unwrap_or_else(|| self.config.interval_unit.resolution());
+        let min_std = min_std.num_microseconds().unwrap_or(i64::MAX) as f64 / 1000.;
+        // max also maps a NaN variance to the floor
+        (variance.max(0.).max(min_std * min_std), mean)
+    }
+
+    fn learned_distribution(&self, stats: &Statistics) -> (f64, f64) {
+        let (mut variance, mut mean) = match (self.config.trimmed_fraction, self.config.outlier_rejection) {
+            _ if self.config.median_spread => stats.variance_and_median_mad(),
+            (Some(f), _) => stats.variance_and_mean_trimmed(f),
+            (None, Some(k)) => stats.variance_and_mean_within(k),
+            (None, None) => stats.variance_and_mean(),
+        };
+        let per_ms = stats.unit.per_ms() as f64;
+        variance /= per_ms * per_ms;
+        mean /= per_ms;
+        if let Some(prior) = self.config.prior {
+            let n = stats.arrival_intervals.len() as f64;
+            let total = PRIOR_WEIGHT + n;
+            let pooled_mean = (PRIOR_WEIGHT * prior.mean + n * mean) / total;
+            variance = (PRIOR_WEIGHT * (prior.variance + (prior.mean - pooled_mean).powi(2))
+                + n * (variance + (mean - pooled_mean).powi(2))) / total;
+            mean = pooled_mean;
+        }
+        (variance, mean)
+    }
+
+    fn elapsed_ms_between(&self, last_arrived_at: DateTime<Local>, t: DateTime<Local>) -> f64 {
+        let mut elapsed = t.sub(last_arrived_at);
+        if let Some(delay) = self.one_way_delay() {
+            // last_arrived_at is the sender's stamp, measure from its receipt instead
+            elapsed -= delay;
+        }
+        match self.config.interval_unit {
+            IntervalUnit::Milliseconds => elapsed.num_milliseconds() as f64,
+            IntervalUnit::Microseconds => elapsed.num_microseconds().unwrap_or(i64::MAX) as f64 / 1000.,
+        }
+    }
+
+    fn pause_ms(&self, variance: f64) -> f64 {
+        let fixed = self.acceptable_pause.load(Ordering::Relaxed) as f64 / 1000.;
+        match self.config.adaptive_pause {
+            Some(k) if self.config.pause_model_base => fixed + k * variance.sqrt(),
+            Some(k) => k * variance.sqrt(),
+            None => fixed,
+        }
+    }
+
+    fn apply_pause(&self, elapsed_ms: f64, variance: f64, mean: f64) -> (f64, f64) {
+        let pause_ms = self.pause_ms(variance);
+        match self.config.pause_mode {
+            PauseMode::SubtractFromElapsed => (elapsed_ms - pause_ms, mean),
+            PauseMode::ExtendMean => (elapsed_ms, mean + pause_ms),
+            PauseMode::LateOnly if elapsed_ms <= mean => (elapsed_ms, mean),
+            PauseMode::LateOnly => ((elapsed_ms - pause_ms).max(mean), mean),
+        }
+    }
+
+    fn warm_heartbeat(&self, stats: &Statistics) -> Result<DateTime<Local>, PhiError> {
+        if stats.arrival_intervals.len() < self.config.min_samples {
+            return Err(PhiError::NotEnoughSamples);
+        }
+        stats.last_heartbeat().ok_or(PhiError::NotEnoughSamples)
+    }
+
+    fn phi_with_stats(&self, stats: &Statistics, t: DateTime<Local>) -> Result<f64, PhiError> {
+        let last_arrived_at = self.warm_heartbeat(stats)?;
+        let (sigma_sq, mu) = self.distribution(stats);
+        Ok(self.phi_at(self.elapsed_ms_between(last_arrived_at, t), sigma_sq, mu))
+    }
+
+    fn phi_at(&self, elapsed_ms: f64, variance: f64, mean: f64) -> f64 {
+        let (elapsed_ms, mean) = self.apply_pause(elapsed_ms, variance, mean);
+        self.raw_phi_at(elapsed_ms, variance, mean)
+    }
+
+    fn raw_phi_at(&self, elapsed_ms: f64, variance: f64, mean: f64) -> f64 {
+        let phi = self.uncapped_phi(elapsed_ms, variance, mean);
+        match self.config.max_phi {
+            Some(max_phi) => phi.min(max_phi),
+            None => phi,
+        }
+    }
+
+    fn uncapped_phi(&self, elapsed_ms: f64, variance: f64, mean: f64) -> f64 {
+        let variance = match self.config.idle_decay {
+            Some((factor, after)) if elapsed_ms > after * mean => variance * factor,
+            _ => variance,
+        };
+        let samples = self.samples.load(Ordering::Relaxed);
+        let phi = match self.config.student_t_below {
+            Some(below) if samples < below && samples >= 2 && variance > 0. => {
+                let t = (elapsed_ms - mean) / variance.sqrt();
+                self.config.base.neg_log(student_t_sf(t, (samples - 1) as f64))
+            }
+            _ => phi_from_params(elapsed_ms, mean, variance, self.config.base),
+        };
+        let phi = match self.config.reference {
+            PhiReference::LastArrival => phi,
+            PhiReference::NextExpected if elapsed_ms <= mean => 0.,
+            PhiReference::NextExpected => (phi - self.config.base.neg_log(0.5)).max(0.),
+        };
+        phi + self.missed.load(Ordering::Relaxed) as f64 * MISSED_HEARTBEAT_PHI
+    }
+
+    fn refresh(&self, stats: &Statistics) {
+        self.samples.store(stats.arrival_intervals.len(), Ordering::Relaxed);
+        self.window_length.store(stats.window_length, Ordering::Relaxed);
+        let (variance, mean) = self.distribution(stats);
+        self.cache.store(variance, mean, stats.last_heartbeat());
+    }
+}
+
+/// Pause in microseconds, saturating for pauses beyond ~292k years.
+fn pause_micros(pause: TimeDelta) -> i64 {
+    pause.num_microseconds().unwrap_or(if pause < TimeDelta::zero() { i64::MIN } else { i64::MAX })
+}
+
+/// Cumulative distribution function for normal distribution. With zero variance the distribution
+/// collapses onto mu, so the CDF becomes a step: 0 before mu, 0.5 at mu and 1 after it. Early and
+/// on-time heartbeats therefore keep phi low and only late ones saturate it.
+pub fn normal_cdf(t: f64, mu: f64, sigma: f64) -> f64 {
+    if sigma == 0. {
+        return if t < mu {
+            0.
+        } else if t == mu {
+            0.5
+        } else {
+            1.
+        };
+    }
+
+    let z = (t - mu) / sigma;
+    0.5 + 0.5 * erf(z / std::f64::consts::SQRT_2)
+}
+
+/// Survival function `1 - F(t)` of Student's t distribution with dof degrees of freedom, from the
+/// regularized incomplete beta function. The upper tail is computed directly rather than as
+/// `1 - F`, so it doesn't round to 0 as early.
+fn student_t_sf(t: f64, dof: f64) -> f64 {
+    let tail = 0.5 * inc_beta(dof / 2., 0.5, dof / (dof + t * t));
+    if t >= 0. {
+        tail
+    } else {
+        1. - tail
+    }
+}
+
+/// Regularized incomplete beta function `I_x(a, b)`, by its continued fraction.
+fn inc_beta(a: f64, b: f64, x: f64) -> f64 {
+    if x <= 0. {
+        return 0.;
+    }
+    if x >= 1. {
+        return 1.;
+    }
+    let front = exp(lgamma(a + b) - lgamma(a) - lgamma(b) + a * log(x) + b * log(1. - x));
+    // the fraction converges fast on this side of the mean, use the symmetry on the other
+    if x < (a + 1.) / (a + b + 2.) {
+        front * beta_fraction(a, b, x) / a
+    } else {
+        1. - front * beta_fraction(b, a, 1. - x) / b
+    }
+}
+
+/// Continued fraction of the incomplete beta function, evaluated with Lentz's method.
+fn beta_fraction(a: f64, b: f64, x: f64) -> f64 {
+    const TINY: f64 = 1e-300;
+    let nonzero = |v: f64| if v.abs() < TINY { TINY } else { v };
+    let mut c = 1.;
+    let mut d = 1. / nonzero(1. - (a + b) * x / (a + 1.));
+    let mut h = d;
+    for m in 1..=200 {
+        let m = m as f64;
+        let even = m * (b - m) * x / ((a + 2. * m - 1.) * (a + 2. * m));
+        d = 1. / nonzero(1. + even * d);
+        c = nonzero(1. + even / c);
+        h *= d * c;
+        let odd = -(a + m) * (a + b + m) * x / ((a + 2. * m) * (a + 2. * m + 1.));
+        d = 1. / nonzero(1. + odd * d);
+        c = nonzero(1. + odd / c);
+        let delta = d * c;
+        h *= delta;
+        if (delta - 1.).abs() < 1e-15 {
+            break;
+        }
+    }
+    h
+}
+
+/// Phi of a heartbeat elapsed_ms after the last one under a normal distribution with mean and
+/// variance in milliseconds: `-log(1 - F(elapsed_ms))` in the given base. This is the formula a
+/// Detector applies to its window, without the acceptable pause or the max_phi cap. A negative
+/// variance, e.g. from a running sum of squares losing precision, is taken as 0 rather than
+/// turning sigma into NaN.
+///
+/// F saturates a few dozen stds past the mean, so the `1 - F` of an outage of days under a mean
+/// of seconds underflows to exactly 0 and phi is `+inf`, never NaN. A Detector built with
+/// DetectorBuilder::with_max_phi reports its cap instead.
+pub fn phi_from_params(elapsed_ms: f64, mean: f64, variance: f64, base: PhiBase) -> f64 {
+    let ft = normal_cdf(elapsed_ms, mean, variance.max(0.).sqrt());
+    base.neg_log(1. - ft)
+}
+
+/// Implementation of PhiInteraction for Detector
+#[async_trait]
+impl PhiInteraction for Detector {
+    /// Returns the interval recorded in the interval unit. In batching mode, see
+    /// DetectorBuilder::with_insert_batching, the heartbeat is queued and only applied right away
+    /// if the write lock is free, and the interval isn't known in time to be returned.
+    async fn insert(&self, arrived_at: DateTime<Local>) -> Result<Option<u64>, Box<dyn Error>> {
+        #[cfg(feature = "tracing")]
+        let span = tracing::debug_span!(
+            "insert",
+            label = self.label(),
+            %arrived_at,
+            interval_ms = tracing::field::Empty,
+        );
+        let insert = async {
+            if let Some(batch) = &self.batch {
+                // the receiver lives as long as the detector, so this can't fail
+                let _ = batch.tx.send(arrived_at);
+                if let Some(mut stats) = runtime::try_write(&self.statistics) {
+                    self.drain_batch(&mut stats);
+                }
+                return Ok(None);
+            }
+            let mut stats = self.statistics.write().await;
+            let recorded = self.record_heartbeat(&mut stats, arrived_at);
+            self.heartbeat_inserted(&stats, recorded);
+            #[cfg(feature = "tracing")]
+            {
+                tracing::Span::current().record("interval_ms", tracing::field::debug(recorded));
+                tracing::debug!(label = self.label(), interval_ms = ?recorded, %arrived_at, "heartbeat inserted");
+            }
+            Ok(recorded)
+        };
+        #[cfg(feature = "tracing")]
+        let insert = tracing::Instrument::instrument(insert, span);
+        insert.await
+    }
+
+    /// Phi at t from the distribution cached by the last write, without taking the lock, unless
+    /// overridden by force_down or force_up or suppressed by the startup grace. Any t is
+    /// supported: phi is non-decreasing in t, grows to infinity (or max_phi) for t far in the
+    /// future and is near 0 for t before the last heartbeat. Fails with
+    /// PhiError::NotEnoughSamples before the first heartbeat.
+    async fn phi(&self, t: DateTime<Local>) -> Result<f64, Box<dyn Error>> {
+        #[cfg(feature = "tracing")]
+        let span = tracing::debug_span!("phi", label = self.label(), %t, phi = tracing::field::Empty);
+        #[cfg(feature = "tracing")]
+        let _entered = span.enter();
+        if let Some(phi) = self.overridden_phi(t) {
+            #[cfg(feature = "tracing")]
+            span.record("phi", phi);
+            return Ok(phi);
+        }
+        let (variance, mean, last_arrived_at) = self.warm_cache()?;
+        let phi = self.phi_at(self.elapsed_ms_between(last_arrived_at, t), variance, mean);
+        #[cfg(feature = "tracing")]
+        {
+            span.record("phi", phi);
+            tracing::debug!(label = self.label(), phi, mean, std = variance.sqrt(), "phi evaluated");
+        }
+        Ok(phi)
+    }
+
+    /// Fails with PhiError::NotEnoughSamples before the first heartbeat.
+    async fn last_arrived_at(&self) -> Result<DateTime<Local>, Box<dyn Error>> {
+        let last_arrived_at = self.statistics.read().await.last_heartbeat();
+        Ok(last_arrived_at.ok_or(PhiError::NotEnoughSamples)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ops::{Add, Sub};
+    use std::sync::Arc;
+    use chrono::{DateTime, Duration, Local, NaiveDate, TimeDelta};
+    use crate::{normal_cdf, phi_from_params, student_t_sf, Clock, CompactIntervals, Detector, DetectorBuilder, IntervalStore, MISSED_HEARTBEAT_PHI, IntervalUnit, MinIntervalPolicy, PauseMode, PhiBase, PhiCore, PhiInteraction, PhiError, PhiReference, NodeState, RateChange, Severity, StateTransition, Statistics, StatsSnapshot};
+
+    /// Clock that only moves when advanced.
+    #[derive(Debug)]
+    struct MockClock(std::sync::Mutex<DateTime<Local>>);
+
+    impl MockClock {
+        fn new() -> Arc<Self> {
+            Arc::new(MockClock(std::sync::Mutex::new(Local::now())))
+        }
+
+        fn advance(&self, ms: i64) {
+            *self.0.lock().unwrap() += TimeDelta::milliseconds(ms);
+        }
+    }
+
+    impl Clock for MockClock {
+        fn now(&self) -> DateTime<Local> {
+            *self.0.lock().unwrap()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_variant_mean_and_variance_combo_calculation() {
+        let mut stats = Statistics::new(10);
+        let mut i = 0;
+        let mut curr_time = Local::now();
+        stats.insert(curr_time);
+        let expect_vals = [1630, 4421, 1514, 216, 231, 931, 4182, 102, 104, 241, 5132];
+        while i < expect_vals.len() {
+            curr_time = curr_time.add(Duration::milliseconds(expect_vals[i]));
+            let arrived_at = curr_time;
+            stats.insert(arrived_at);
+            i += 1;
+        }
+        let detector = Detector::from_parts(stats, TimeDelta::milliseconds(0), Default::default());
+        let (mut variance, mut mean) = detector.statistics.read().await.variance_and_mean();
+        mean = (mean * 100.0).round() * 0.01;
+        variance = (variance * 100.0).round() * 0.01;
+        assert_eq!(1707.4, mean);
+        assert_eq!(3755791.64, variance);
+
+        let mut suspicion_level: Vec<f64> = vec![];
+        for _ in 1..10 {
+            curr_time = curr_time.add(Duration::milliseconds(250));
+            suspicion_level.push(detector.phi(curr_time).await.unwrap())
+        }
+        println!("suspicion -> {:?}", suspicion_level);
+        for i in 1..suspicion_level.len() {
+            assert!(suspicion_level[i] > suspicion_level[i - 1]);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_constant_phi_with_constant_pings_calculation() {
+        let stats = Statistics::new(10);
+        let detector = Detector::from_parts(stats, TimeDelta::milliseconds(0), Default::default());
+        let mut i = 0;
+        let mut curr_time = Local::now();
+        while i <= 100 {
+            let arrived_at = curr_time;
+            detector.insert(arrived_at).await.unwrap();
+            curr_time = curr_time.add(Duration::milliseconds(10));
+            i += 10;
+        }
+        let (mut variance, mut mean) = detector.statistics.read().await.variance_and_mean();
+        mean = (mean * 100.0).round() * 0.01;
+        variance = (variance * 100.0).round() * 0.01;
+        assert_eq!(10., mean);
+        assert_eq!(0., variance);
+        curr_time = curr_time.add(Duration::milliseconds(10));
+        assert!(detector.phi(curr_time).await.unwrap().is_infinite());
+    }
+
+    #[tokio::test]
+    async fn test_subscribe_streams_phi_samples() {
+        let detector = Arc::new(Detector::new(10));
+        let now = Local::now();
+        for offset in [400, 290, 210, 100] {
+            detector.insert(now.sub(Duration::milliseconds(offset))).await.unwrap();
+        }
+
+        let mut rx = detector.subscribe(std::time::Duration::from_millis(10));
+        let mut samples = vec![];
+        for _ in 0..3 {
+            samples.push(rx.recv().await.expect("sampling task stopped early"));
+        }
+        for i in 1..samples.len() {
+            assert!(samples[i].timestamp > samples[i - 1].timestamp);
+            assert!(samples[i].phi >= samples[i - 1].phi);
+        }
+
+        drop(rx);
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        assert_eq!(1, Arc::strong_count(&detector));
+    }
+
+    #[tokio::test]
+    async fn test_merge_keeps_most_recent_intervals() {
+        let start = Local::now();
+        let older = Detector::new(10);
+        let newer = Detector::new(10);
+        let mut t = start;
+        for interval in [100, 200, 300] {
+            older.insert(t).await.unwrap();
+            t = t.add(Duration::milliseconds(interval));
+        }
+        older.insert(t).await.unwrap();
+        for interval in [400, 500] {
+            newer.insert(t).await.unwrap();
+            t = t.add(Duration::milliseconds(interval));
+        }
+        newer.insert(t).await.unwrap();
+
+        older.merge(&newer).await.unwrap();
+        let (_, mean) = older.statistics.read().await.variance_and_mean();
+        assert_eq!(300., mean);
+        assert_eq!(t, older.last_arrived_at().await.unwrap());
+
+        let mut small = Statistics::new(2);
+        small.merge(&*older.statistics.read().await);
+        assert_eq!(vec![400, 500], small.intervals().collect::<Vec<_>>());
+        assert_eq!(3, small.n);
+    }
+
+    #[tokio::test]
+    async fn test_set_acceptable_pause_lowers_phi() {
+        let detector = Detector::new(10);
+        let mut t = Local::now();
+        for interval in [100, 120, 80, 110, 90] {
+            detector.insert(t).await.unwrap();
+            t = t.add(Duration::milliseconds(interval));
+        }
+        detector.insert(t).await.unwrap();
+
+        let query_at = t.add(Duration::milliseconds(150));
+        let strict = detector.phi(query_at).await.unwrap();
+        detector.set_acceptable_pause(TimeDelta::milliseconds(100));
+        assert_eq!(TimeDelta::milliseconds(100), detector.acceptable_pause());
+        let lenient = detector.phi(query_at).await.unwrap();
+        assert!(lenient < strict);
+    }
+
+    #[tokio::test]
+    async fn test_failure_probability_decreases_with_phi() {
+        let detector = Detector::new(10);
+        let mut t = Local::now();
+        for interval in [100, 120, 80, 110, 90] {
+            detector.insert(t).await.unwrap();
+            t = t.add(Duration::milliseconds(interval));
+        }
+        detector.insert(t).await.unwrap();
+
+        let mut last_phi = f64::NEG_INFINITY;
+        let mut last_probability = f64::INFINITY;
+        for offset in (0..200).step_by(20) {
+            let query_at = t.add(Duration::milliseconds(offset));
+            let phi = detector.phi(query_at).await.unwrap();
+            let probability = detector.failure_probability(query_at).await.unwrap();
+            assert!((0. ..=1.).contains(&probability));
+            assert!(phi >= last_phi);
+            assert!(probability <= last_probability);
+            last_phi = phi;
+            last_probability = probability;
+        }
+    }
+
+    #[tokio::test]
+    async fn test_clock_regression_is_counted() {
+        let detector = Detector::new(10);
+        let t = Local::now();
+        detector.insert(t).await.unwrap();
+        detector.insert(t.add(Duration::milliseconds(100))).await.unwrap();
+        assert_eq!(0, detector.clock_regression_count().await);
+
+        detector.insert(t.add(Duration::milliseconds(50))).await.unwrap();
+        assert_eq!(1, detector.clock_regression_count().await);
+        assert_eq!(t.add(Duration::milliseconds(100)), detector.last_arrived_at().await.unwrap());
+        assert_eq!(vec![100], detector.statistics.read().await.intervals().collect::<Vec<_>>());
+    }
+
+    #[tokio::test]
+    async fn test_ewma_mean_tracks_step_change_faster() {
+        let uniform = Detector::new(100);
+        let ewma = Detector::with_ewma(100, 0.3);
+        let mut t = Local::now();
+        let intervals = [100; 20].into_iter().chain([500; 5]);
+        for interval in intervals {
+            uniform.insert(t).await.unwrap();
+            ewma.insert(t).await.unwrap();
+            t = t.add(Duration::milliseconds(interval));
+        }
+        uniform.insert(t).await.unwrap();
+        ewma.insert(t).await.unwrap();
+
+        let (_, uniform_mean) = uniform.statistics.read().await.variance_and_mean();
+        let (ewma_variance, ewma_mean) = ewma.statistics.read().await.variance_and_mean();
+        assert!((500. - ewma_mean).abs() < (500. - uniform_mean).abs());
+        assert!(ewma_mean > 400.);
+        assert!(ewma_variance > 0.);
+    }
+
+    #[tokio::test]
+    async fn test_on_time_heartbeat_with_zero_variance_has_low_phi() {
+        let detector = Detector::new(10);
+        let mut t = Local::now();
+        for _ in 0..=10 {
+            detector.insert(t).await.unwrap();
+            t = t.add(Duration::milliseconds(500));
+        }
+        let last = t.sub(Duration::milliseconds(500));
+
+        let on_time = detector.phi(t).await.unwrap();
+        assert!(on_time.is_finite());
+        assert!(on_time < 0.5);
+        assert_eq!(0., detector.phi(last.add(Duration::milliseconds(250))).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_max_phi_caps_long_silence() {
+        let detector = Detector::builder(10).with_max_phi(1e6).build();
+        let uncapped = Detector::new(10);
+        let mut t = Local::now();
+        for interval in [100, 120, 80, 110, 90] {
+            detector.insert(t).await.unwrap();
+            uncapped.insert(t).await.unwrap();
+            t = t.add(Duration::milliseconds(interval));
+        }
+        detector.insert(t).await.unwrap();
+        uncapped.insert(t).await.unwrap();
+
+        let silence = t.add(Duration::seconds(60));
+        assert!(uncapped.phi(silence).await.unwrap().is_infinite());
+        assert_eq!(1e6, detector.phi(silence).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_status_is_internally_consistent() {
+        let detector = Detector::new(10);
+        let mut t = Local::now();
+        for interval in [100, 120, 80, 110, 90] {
+            detector.insert(t).await.unwrap();
+            t = t.add(Duration::milliseconds(interval));
+        }
+        detector.insert(t).await.unwrap();
+
+        let query_at = t.add(Duration::milliseconds(130));
+        let status = detector.status(query_at).await.unwrap();
+        let (variance, mean) = detector.statistics.read().await.variance_and_mean();
+        assert_eq!(mean, status.mean);
+        assert_eq!(variance.sqrt(), status.std);
+        assert!(status.std > 1.);
+        assert_eq!(5, status.sample_count);
+        assert_eq!(t, status.last_arrived_at);
+        assert_eq!(Some(90), status.last_interval);
+        assert_eq!(detector.phi(query_at).await.unwrap(), status.phi);
+    }
+
+    #[tokio::test]
+    async fn test_clone_snapshots_then_diverges() {
+        let detector = Detector::with_acceptable_pause(10, TimeDelta::milliseconds(20));
+        let mut t = Local::now();
+        for interval in [100, 120, 80, 110, 90] {
+            detector.insert(t).await.unwrap();
+            t = t.add(Duration::milliseconds(interval));
+        }
+        detector.insert(t).await.unwrap();
+
+        let fork = detector.clone();
+        let query_at = t.add(Duration::milliseconds(150));
+        assert_eq!(detector.phi(query_at).await.unwrap(), fork.phi(query_at).await.unwrap());
+        assert_eq!(detector.acceptable_pause(), fork.acceptable_pause());
+
+        fork.insert(t.add(Duration::milliseconds(140))).await.unwrap();
+        assert_ne!(detector.phi(query_at).await.unwrap(), fork.phi(query_at).await.unwrap());
+        assert_eq!(t, detector.last_arrived_at().await.unwrap());
+
+        // a heartbeat ended the grace and the lost ones stay reported in the clone
+        let detector = Detector::builder(10).with_startup_grace(TimeDelta::seconds(60)).build();
+        let t = Local::now();
+        for ms in [0, 100, 220, 300, 410] {
+            detector.insert(t + Duration::milliseconds(ms)).await.unwrap();
+        }
+        detector.report_missed_heartbeats(3);
+        let query_at = t + Duration::milliseconds(450);
+        for fork in [detector.clone(), detector.snapshot_clone().await] {
+            assert_eq!(detector.phi(query_at).await.unwrap(), fork.phi(query_at).await.unwrap());
+            assert!(fork.phi(query_at).await.unwrap() > 3.);
+            assert!(fork.phi(t + Duration::seconds(10)).await.unwrap() > 8.);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_snapshot_clone_waits_for_view() {
+        let detector = Arc::new(Detector::new(10));
+        let t = Local::now();
+        for ms in [0, 100, 220, 300] {
+            detector.insert(t + Duration::milliseconds(ms)).await.unwrap();
+        }
+        let view = detector.view().await;
+        let writer = {
+            let detector = detector.clone();
+            tokio::spawn(async move { detector.insert(t + Duration::milliseconds(410)).await.unwrap() })
+        };
+        tokio::task::yield_now().await;
+        let fork = {
+            let detector = detector.clone();
+            tokio::spawn(async move { detector.snapshot_clone().await })
+        };
+        tokio::task::yield_now().await;
+        assert_eq!(3, view.sample_count());
+        drop(view);
+        writer.await.unwrap();
+        let fork = fork.await.unwrap();
+        assert_eq!(detector.stats_snapshot().await, fork.stats_snapshot().await);
+        assert_eq!(4, fork.statistics.read().await.len());
+    }
+
+    #[tokio::test]
+    async fn test_phi_base_ratio_is_ln_10() {
+        let log10_detector = Detector::new(10);
+        let ln_detector = Detector::builder(10).with_phi_base(PhiBase::Ln).build();
+        let mut t = Local::now();
+        for interval in [100, 120, 80, 110, 90] {
+            log10_detector.insert(t).await.unwrap();
+            ln_detector.insert(t).await.unwrap();
+            t = t.add(Duration::milliseconds(interval));
+        }
+        log10_detector.insert(t).await.unwrap();
+        ln_detector.insert(t).await.unwrap();
+
+        let query_at = t.add(Duration::milliseconds(130));
+        let ratio = ln_detector.phi(query_at).await.unwrap() / log10_detector.phi(query_at).await.unwrap();
+        assert!((ratio - std::f64::consts::LN_10).abs() < 1e-9);
+    }
+
+    #[tokio::test]
+    async fn test_insert_interval_matches_timestamp_inserts() {
+        let by_interval = Detector::new(4);
+        let by_timestamp = Detector::new(4);
+        let mut t = Local::now();
+        by_timestamp.insert(t).await.unwrap();
+        for interval in [100, 120, 80, 110, 90] {
+            by_interval.insert_interval(interval).await.unwrap();
+            t = t.add(Duration::milliseconds(interval as i64));
+            by_timestamp.insert(t).await.unwrap();
+        }
+
+        let interval_stats = by_interval.statistics.read().await;
+        let timestamp_stats = by_timestamp.statistics.read().await;
+        assert_eq!(timestamp_stats.variance_and_mean(), interval_stats.variance_and_mean());
+        assert!(timestamp_stats.intervals().eq(interval_stats.intervals()));
+        assert_eq!(timestamp_stats.n, interval_stats.n);
+    }
+
+    #[tokio::test]
+    async fn test_prior_dampens_early_phi_volatility() {
+        let plain = Detector::new(100);
+        let seeded = Detector::builder(100).with_prior(100., 20.).build();
+        let mut t = Local::now();
+        plain.insert(t).await.unwrap();
+        seeded.insert(t).await.unwrap();
+
+        let mut plain_phi = vec![];
+        let mut seeded_phi = vec![];
+        for interval in [100, 60, 140, 90, 120, 70, 130, 110, 80, 100] {
+            t = t.add(Duration::milliseconds(interval));
+            plain.insert(t).await.unwrap();
+            seeded.insert(t).await.unwrap();
+            let query_at = t.add(Duration::milliseconds(120));
+            plain_phi.push(plain.phi(query_at).await.unwrap());
+            seeded_phi.push(seeded.phi(query_at).await.unwrap());
+        }
+
+        let spread = |phi: &[f64]| phi.iter().cloned().fold(f64::MIN, f64::max) - phi.iter().cloned().fold(f64::MAX, f64::min);
+        assert!(seeded_phi.iter().all(|phi| phi.is_finite()));
+        assert!(spread(&seeded_phi) < spread(&plain_phi));
+    }
+
+    #[test]
+    fn test_normal_cdf_matches_standard_normal_table() {
+        let round = |v: f64| (v * 1000.).round() / 1000.;
+        assert_eq!(0.5, normal_cdf(0., 0., 1.));
+        assert_eq!(0.841, round(normal_cdf(1., 0., 1.)));
+        assert_eq!(0.159, round(normal_cdf(-1., 0., 1.)));
+        assert_eq!(0.977, round(normal_cdf(2., 0., 1.)));
+        assert_eq!(0.975, round(normal_cdf(1.96, 0., 1.)));
+        // z is scaled by sigma and shifted by mu
+        assert_eq!(0.841, round(normal_cdf(600., 500., 100.)));
+    }
+
+    #[test]
+    fn test_phi_from_params() {
+        assert_eq!(-(0.5f64).log10(), phi_from_params(500., 500., 100. * 100., PhiBase::Log10));
+        let one_sigma = phi_from_params(600., 500., 100. * 100., PhiBase::Log10);
+        assert_eq!(0.8, (one_sigma * 10.).round() / 10.);
+        let ratio = phi_from_params(600., 500., 100. * 100., PhiBase::Ln) / one_sigma;
+        assert!((ratio - std::f64::consts::LN_10).abs() < 1e-9);
+    }
+
+    #[tokio::test]
+    async fn test_identical_intervals_give_smoothly_rising_phi() {
+        let detector = Detector::new(10);
+        let mut t = Local::now();
+        for _ in 0..=10 {
+            detector.insert(t).await.unwrap();
+            t = t.add(Duration::milliseconds(500));
+        }
+        let last = t.sub(Duration::milliseconds(500));
+
+        let mut previous = detector.phi(last.add(Duration::milliseconds(498))).await.unwrap();
+        for elapsed in 499..=505 {
+            let phi = detector.phi(last.add(Duration::milliseconds(elapsed))).await.unwrap();
+            assert!(phi.is_finite());
+            assert!(phi > previous);
+            previous = phi;
+        }
+
+        let stepped = Detector::builder(10).with_min_std_deviation(TimeDelta::zero()).build();
+        let mut t = last.sub(Duration::milliseconds(5000));
+        for _ in 0..=10 {
+            stepped.insert(t).await.unwrap();
+            t = t.add(Duration::milliseconds(500));
+        }
+        assert!(stepped.phi(last.add(Duration::milliseconds(501))).await.unwrap().is_infinite());
+    }
+
+    #[tokio::test]
+    async fn test_interval_history_keeps_arrival_times() {
+        let detector = Detector::new(3);
+        let mut t = Local::now();
+        let mut arrivals = vec![];
+        detector.insert(t).await.unwrap();
+        for interval in [100, 120, 80, 110] {
+            t = t.add(Duration::milliseconds(interval));
+            detector.insert(t).await.unwrap();
+            arrivals.push((t, interval as u64));
+        }
+
+        // only window_length intervals are kept
+        assert_eq!(arrivals[1..].to_vec(), detector.interval_history().await);
+
+        detector.insert_interval(50).await.unwrap();
+        let history = detector.interval_history().await;
+        assert_eq!((t.add(Duration::milliseconds(50)), 50), history[2]);
+        assert_eq!(3, history.len());
+    }
+
+    #[tokio::test]
+    async fn test_phi_does_not_wait_for_write_lock() {
+        let detector = Detector::new(10);
+        let mut t = Local::now();
+        for interval in [100, 120, 80, 110, 90] {
+            detector.insert(t).await.unwrap();
+            t = t.add(Duration::milliseconds(interval));
+        }
+        detector.insert(t).await.unwrap();
+        let query_at = t.add(Duration::milliseconds(130));
+        let expected = detector.phi_with_stats(&*detector.statistics.read().await, query_at).unwrap();
+
+        let _writer = detector.statistics.write().await;
+        let phi = tokio::time::timeout(std::time::Duration::from_millis(100), detector.phi(query_at))
+            .await
+            .expect("phi blocked on the write lock")
+            .unwrap();
+        assert_eq!(expected, phi);
+    }
+
+    #[test]
+    #[should_panic(expected = "window_length must be at least 1")]
+    fn test_zero_window_length_is_rejected() {
+        Detector::new(0);
+    }
+
+    #[tokio::test]
+    async fn test_phi_trend_rises_when_going_silent() {
+        let detector = Detector::new(10);
+        let mut t = Local::now();
+        for interval in [100, 120, 80, 110, 90] {
+            detector.insert(t).await.unwrap();
+            t = t.add(Duration::milliseconds(interval));
+        }
+        detector.insert(t).await.unwrap();
+
+        let step = TimeDelta::milliseconds(10);
+        let degrading = detector.phi_trend(5, step, t.add(Duration::milliseconds(150))).await.unwrap();
+        assert!(degrading > 1.);
+        let healthy = detector.phi_trend(5, step, t.add(Duration::milliseconds(40))).await.unwrap();
+        assert!(healthy.abs() < 1e-3);
+
+        let err = detector.phi_trend(1, step, t).await.unwrap_err();
+        assert_eq!(Some(&PhiError::NotEnoughSamples), err.downcast_ref::<PhiError>());
+        for (samples, interval) in [(3, TimeDelta::milliseconds(i64::MAX)), (3, TimeDelta::milliseconds(-i64::MAX)), (usize::MAX, step), (1 << 20, TimeDelta::days(1 << 20))] {
+            let err = detector.phi_trend(samples, interval, t).await.unwrap_err();
+            assert!(matches!(err.downcast_ref::<PhiError>(), Some(PhiError::InvalidConfig(_))));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_pause_modes_agree_at_same_timestamp() {
+        let pause = TimeDelta::milliseconds(50);
+        let strict = Detector::new(10);
+        let subtracting = Detector::with_acceptable_pause(10, pause);
+        let extending = Detector::builder(10)
+            .with_acceptable_pause(pause)
+            .with_pause_mode(PauseMode::ExtendMean)
+            .build();
+        let mut t = Local::now();
+        for interval in [100, 120, 80, 110, 90] {
+            for detector in [&strict, &subtracting, &extending] {
+                detector.insert(t).await.unwrap();
+            }
+            t = t.add(Duration::milliseconds(interval));
+        }
+        for detector in [&strict, &subtracting, &extending] {
+            detector.insert(t).await.unwrap();
+        }
+
+        for elapsed in [10, 100, 150, 200] {
+            let query_at = t.add(Duration::milliseconds(elapsed));
+            let subtracted = subtracting.phi(query_at).await.unwrap();
+            let extended = extending.phi(query_at).await.unwrap();
+            assert!((subtracted - extended).abs() < 1e-9);
+            assert!(extended <= strict.phi(query_at).await.unwrap());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_describe_healthy_and_suspect() {
+        let detector = Detector::new(10);
+        let mut t = Local::now();
+        for interval in [500, 540, 460, 520, 480] {
+            detector.insert(t).await.unwrap();
+            t = t.add(Duration::milliseconds(interval));
+        }
+        detector.insert(t).await.unwrap();
+
+        let description = detector.describe(t.add(Duration::milliseconds(480))).await.unwrap();
+        assert!(description.starts_with("UP ("), "{}", description);
+        for token in ["mean=500ms", "std=28ms", "5 samples", "last 480ms ago"] {
+            assert!(description.contains(token), "{} missing {}", description, token);
+        }
+        let description = detector.describe(t.add(Duration::seconds(5))).await.unwrap();
+        assert!(description.starts_with("SUSPECT ("), "{}", description);
+    }
+
+    #[tokio::test]
+    async fn test_adaptive_pause_tolerates_jittery_node() {
+        let jittery = Detector::builder(10).with_adaptive_pause(3.).build();
+        let stable = Detector::builder(10).with_adaptive_pause(3.).build();
+        let mut jittery_t = Local::now();
+        let mut stable_t = jittery_t;
+        for (jittery_interval, stable_interval) in [(100, 100), (150, 101), (50, 99), (140, 100), (60, 100)] {
+            jittery.insert(jittery_t).await.unwrap();
+            stable.insert(stable_t).await.unwrap();
+            jittery_t = jittery_t.add(Duration::milliseconds(jittery_interval));
+            stable_t = stable_t.add(Duration::milliseconds(stable_interval));
+        }
+        jittery.insert(jittery_t).await.unwrap();
+        stable.insert(stable_t).await.unwrap();
+
+        // both average 100ms, a heartbeat 150ms late is within the jittery node's leeway only
+        let late = Duration::milliseconds(250);
+        assert!(jittery.phi(jittery_t.add(late)).await.unwrap() < 1.);
+        assert!(stable.phi(stable_t.add(late)).await.unwrap() > 8.);
+    }
+
+    #[tokio::test]
+    async fn test_probability_threshold_agrees_with_phi_threshold() {
+        let detector = Detector::builder(10).with_probability_threshold(0.99).build();
+        let default = Detector::builder(10).with_threshold(2.).build();
+        let mut t = Local::now();
+        for interval in [100, 120, 80, 110, 90] {
+            detector.insert(t).await.unwrap();
+            default.insert(t).await.unwrap();
+            t = t.add(Duration::milliseconds(interval));
+        }
+        detector.insert(t).await.unwrap();
+        default.insert(t).await.unwrap();
+
+        let mut crossed = false;
+        for elapsed in (0..300).step_by(3) {
+            let query_at = t.add(Duration::milliseconds(elapsed));
+            let by_phi = detector.phi(query_at).await.unwrap() >= 2.;
+            assert_eq!(by_phi, detector.is_suspected_prob(query_at).await.unwrap());
+            assert_eq!(by_phi, default.is_suspected_prob(query_at).await.unwrap());
+            crossed |= by_phi;
+        }
+        assert!(crossed);
+    }
+
+    #[tokio::test]
+    async fn test_fresh_detector_has_no_last_arrival() {
+        let detector = Detector::new(10);
+        let err = detector.last_arrived_at().await.unwrap_err();
+        assert_eq!(Some(&PhiError::NotEnoughSamples), err.downcast_ref::<PhiError>());
+        let err = detector.phi(Local::now()).await.unwrap_err();
+        assert_eq!(Some(&PhiError::NotEnoughSamples), err.downcast_ref::<PhiError>());
+        assert!(detector.status(Local::now()).await.is_err());
+
+        let t = Local::now();
+        detector.insert(t).await.unwrap();
+        assert_eq!(t, detector.last_arrived_at().await.unwrap());
+        assert!(detector.phi(t).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_min_max_interval() {
+        let detector = Detector::new(3);
+        assert_eq!(None, detector.min_interval_ms().await);
+        assert_eq!(None, detector.max_interval_ms().await);
+        let t = Local::now();
+        for offset in [0, 700, 800, 1100, 1600] {
+            detector.insert(t + TimeDelta::milliseconds(offset)).await.unwrap();
+        }
+        // window holds 100, 300, 500 after 700 was evicted
+        assert_eq!(Some(100), detector.min_interval_ms().await);
+        assert_eq!(Some(500), detector.max_interval_ms().await);
+    }
+
+    #[tokio::test]
+    async fn test_outlier_rejection_recovers_from_single_pause() {
+        let plain = Detector::new(20);
+        let rejecting = Detector::builder(20).with_outlier_rejection(3.).build();
+        let t = Local::now();
+        let mut offset = 0;
+        for i in 0..20 {
+            // one 30s GC pause among otherwise regular 100ms heartbeats
+            offset += if i == 10 { 30_000 } else { 100 };
+            plain.insert(t + TimeDelta::milliseconds(offset)).await.unwrap();
+            rejecting.insert(t + TimeDelta::milliseconds(offset)).await.unwrap();
+        }
+        let query_at = t + TimeDelta::milliseconds(offset + 500);
+        let plain_phi = plain.phi(query_at).await.unwrap();
+        let rejecting_phi = rejecting.phi(query_at).await.unwrap();
+        assert!(plain_phi < 1., "{}", plain_phi);
+        assert!(rejecting_phi > 8., "{}", rejecting_phi);
+    }
+
+    #[tokio::test]
+    async fn test_with_intervals() {
+        let detector = Detector::new(10);
+        let t = Local::now();
+        for offset in [0, 100, 250, 600] {
+            detector.insert(t + TimeDelta::milliseconds(offset)).await.unwrap();
+        }
+        let sum = detector.with_intervals(|intervals| intervals.iter().sum::<u64>()).await;
+        let cloned: Vec<u64> = detector.interval_history().await.into_iter().map(|(_, interval)| interval).collect();
+        assert_eq!(cloned.iter().sum::<u64>(), sum);
+        assert_eq!(600, sum);
+    }
+
+    #[tokio::test]
+    async fn test_epoch_millis_matches_datetime() {
+        let by_epoch = Detector::new(10);
+        let by_datetime = Detector::new(10);
+        let start = Local::now().timestamp_millis();
+        for offset in [0, 100, 250, 600] {
+            by_epoch.insert_epoch_millis(start + offset).await.unwrap();
+            let t = DateTime::from_timestamp_millis(start + offset).unwrap().with_timezone(&Local);
+            by_datetime.insert(t).await.unwrap();
+        }
+        assert_eq!(by_datetime.last_arrived_at().await.unwrap(), by_epoch.last_arrived_at().await.unwrap());
+        assert_eq!(by_datetime.interval_history().await, by_epoch.interval_history().await);
+        let query_at = start + 900;
+        let t = DateTime::from_timestamp_millis(query_at).unwrap().with_timezone(&Local);
+        assert_eq!(by_datetime.phi(t).await.unwrap(), by_epoch.phi_at_epoch_millis(query_at).await.unwrap());
+
+        let err = by_epoch.insert_epoch_millis(i64::MAX).await.unwrap_err();
+        assert_eq!(Some(&PhiError::InvalidTimestamp(i64::MAX)), err.downcast_ref::<PhiError>());
+    }
+
+    #[cfg(feature = "tracing")]
+    #[tokio::test]
+    async fn test_tracing_events() {
+        use std::collections::HashMap;
+        use std::sync::Mutex;
+        use tracing::field::{Field, Visit};
+        use tracing_subscriber::layer::{Context, Layer, SubscriberExt};
+
+        type Events = Arc<Mutex<Vec<HashMap<String, String>>>>;
+        type Spans = Arc<Mutex<Vec<(tracing::span::Id, HashMap<String, String>)>>>;
+
+        struct Fields<'a>(&'a mut HashMap<String, String>);
+
+        impl Visit for Fields<'_> {
+            fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+                self.0.insert(field.name().to_string(), format!("{:?}", value));
+            }
+        }
+
+        struct Capture(Events, Spans);
+
+        impl<S> Layer<S> for Capture
+        where
+            S: tracing::Subscriber + for<'a> tracing_subscriber::registry::LookupSpan<'a>,
+        {
+            fn on_event(&self, event: &tracing::Event<'_>, ctx: Context<'_, S>) {
+                let mut fields = HashMap::new();
+                event.record(&mut Fields(&mut fields));
+                if let Some(span) = ctx.event_span(event) {
+                    fields.insert("span".to_string(), span.name().to_string());
+                }
+                self.0.lock().unwrap().push(fields);
+            }
+
+            fn on_new_span(&self, attrs: &tracing::span::Attributes<'_>, id: &tracing::span::Id, _: Context<'_, S>) {
+                let mut fields = HashMap::new();
+                fields.insert("name".to_string(), attrs.metadata().name().to_string());
+                attrs.record(&mut Fields(&mut fields));
+                self.1.lock().unwrap().push((id.clone(), fields));
+            }
+
+            fn on_record(&self, id: &tracing::span::Id, values: &tracing::span::Record<'_>, _: Context<'_, S>) {
+                // ids are reused once a span closes, the latest span with the id is the open one
+                let mut spans = self.1.lock().unwrap();
+                if let Some((_, fields)) = spans.iter_mut().rev().find(|(span, _)| span == id) {
+                    values.record(&mut Fields(fields));
+                }
+            }
+        }
+
+        let events = Events::default();
+        let spans = Spans::default();
+        let subscriber = tracing_subscriber::registry().with(Capture(events.clone(), spans.clone()));
+        let _guard = tracing::subscriber::set_default(subscriber);
+
+        let detector = Detector::new(10);
+        let t = Local::now();
+        detector.insert(t).await.unwrap();
+        detector.insert(t + TimeDelta::milliseconds(100)).await.unwrap();
+        detector.phi(t + TimeDelta::milliseconds(200)).await.unwrap();
+        let labeled = Detector::builder(10).with_label("node-a").build();
+        labeled.insert(t).await.unwrap();
+        labeled.phi(t + TimeDelta::milliseconds(200)).await.unwrap();
+
+        let events = events.lock().unwrap();
+        assert_eq!(5, events.len());
+        assert_eq!("None", events[0]["interval_ms"]);
+        assert_eq!("Some(100)", events[1]["interval_ms"]);
+        assert_eq!("heartbeat inserted", events[1]["message"]);
+        assert_eq!("100.0", events[2]["mean"]);
+        assert!(events[2].contains_key("phi"));
+        assert!(events[2].contains_key("std"));
+        assert!(!events[2].contains_key("label"));
+        assert_eq!("\"node-a\"", events[3]["label"]);
+        assert_eq!("\"node-a\"", events[4]["label"]);
+        assert_eq!("insert", events[1]["span"]);
+        assert_eq!("phi", events[2]["span"]);
+
+        let spans: Vec<_> = spans.lock().unwrap().iter().map(|(_, fields)| fields.clone()).collect();
+        assert_eq!(5, spans.len());
+        assert_eq!("insert", spans[1]["name"]);
+        assert_eq!("Some(100)", spans[1]["interval_ms"]);
+        assert_eq!((t + TimeDelta::milliseconds(100)).to_string(), spans[1]["arrived_at"]);
+        assert!(!spans[1].contains_key("label"));
+        assert_eq!("phi", spans[2]["name"]);
+        assert_eq!(events[2]["phi"], spans[2]["phi"]);
+        assert_eq!("\"node-a\"", spans[3]["label"]);
+        assert_eq!("\"node-a\"", spans[4]["label"]);
+        assert!(spans[4].contains_key("phi"));
+    }
+
+    #[tokio::test]
+    async fn test_force_down_overrides_recent_heartbeats() {
+        let detector = Detector::new(10);
+        let t = Local::now();
+        for offset in [0, 100, 200, 300] {
+            detector.insert(t + TimeDelta::milliseconds(offset)).await.unwrap();
+        }
+        let query_at = t + TimeDelta::milliseconds(310);
+        assert!(!detector.is_suspected(query_at).await.unwrap());
+
+        detector.force_down();
+        assert!(detector.is_suspected(query_at).await.unwrap());
+        assert_eq!(f64::INFINITY, detector.phi(query_at).await.unwrap());
+
+        detector.force_up();
+        assert!(!detector.is_suspected(t + TimeDelta::milliseconds(60_000)).await.unwrap());
+
+        detector.clear_override();
+        assert!(detector.is_suspected(t + TimeDelta::milliseconds(60_000)).await.unwrap());
+
+        // the next heartbeat clears the override
+        detector.force_down();
+        detector.insert(t + TimeDelta::milliseconds(400)).await.unwrap();
+        assert!(!detector.is_suspected(t + TimeDelta::milliseconds(410)).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_phi_detailed_matches_phi() {
+        let detector = Detector::with_acceptable_pause(10, TimeDelta::milliseconds(50));
+        let t = Local::now();
+        for offset in [0, 100, 250, 300, 420] {
+            detector.insert(t + TimeDelta::milliseconds(offset)).await.unwrap();
+        }
+        let query_at = t + TimeDelta::milliseconds(600);
+        let report = detector.phi_detailed(query_at).await.unwrap();
+        assert_eq!(detector.phi(query_at).await.unwrap(), report.phi);
+        assert_eq!(105., report.mean);
+        assert_eq!(report.variance.sqrt(), report.std);
+        assert_eq!(180., report.elapsed_ms);
+        assert_eq!(4, report.sample_count);
+    }
+
+    #[tokio::test]
+    async fn test_startup_grace_suppresses_phi() {
+        let t = Local::now();
+        let stale = Detector::new(10);
+        for offset in [0, 100, 200, 300] {
+            stale.insert(t - TimeDelta::seconds(60) + TimeDelta::milliseconds(offset)).await.unwrap();
+        }
+        let statistics = stale.statistics.read().await.clone();
+        let config = crate::Config { startup_grace: Some(TimeDelta::seconds(5)), ..Default::default() };
+        let restored = Detector::from_parts(statistics, TimeDelta::zero(), config);
+
+        assert_eq!(0., restored.phi(t).await.unwrap());
+        assert!(!restored.is_suspected(t).await.unwrap());
+        // active once the grace expires
+        assert!(restored.is_suspected(t + TimeDelta::seconds(10)).await.unwrap());
+
+        let fresh = Detector::builder(10).with_startup_grace(TimeDelta::seconds(5)).build();
+        assert_eq!(0., fresh.phi(t).await.unwrap());
+        // or once a heartbeat arrives
+        fresh.insert(t).await.unwrap();
+        fresh.insert(t + TimeDelta::milliseconds(100)).await.unwrap();
+        assert!(fresh.is_suspected(t + TimeDelta::seconds(2)).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_mock_clock_drives_phi() {
+        let clock = MockClock::new();
+        let detector = Detector::builder(10)
+            .with_clock(clock.clone())
+            .with_startup_grace(TimeDelta::milliseconds(500))
+            .build();
+        assert_eq!(0., detector.phi_now().await.unwrap());
+        for _ in 0..5 {
+            detector.insert_now().await.unwrap();
+            clock.advance(100);
+        }
+        // the last heartbeat was 100ms ago, right on the mean
+        assert_eq!(clock.now() - TimeDelta::milliseconds(100), detector.last_arrived_at().await.unwrap());
+        let at_mean = detector.phi_now().await.unwrap();
+        assert_eq!(detector.phi(clock.now()).await.unwrap(), at_mean);
+        clock.advance(1000);
+        assert!(detector.phi_now().await.unwrap() > at_mean);
+        assert!(detector.is_suspected(clock.now()).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_with_history_duration() {
+        let detector = Detector::with_history_duration(TimeDelta::seconds(1), TimeDelta::seconds(60));
+        assert_eq!(60, detector.statistics.read().await.window_length);
+        let detector = Detector::with_history_duration(TimeDelta::milliseconds(300), TimeDelta::seconds(1));
+        assert_eq!(4, detector.statistics.read().await.window_length);
+        let detector = Detector::with_history_duration(TimeDelta::seconds(5), TimeDelta::seconds(1));
+        assert_eq!(1, detector.statistics.read().await.window_length);
+    }
+
+    #[tokio::test]
+    async fn test_effective_deadline_shifts_by_acceptable_pause() {
+        let plain = Detector::new(10);
+        let paused = Detector::with_acceptable_pause(10, TimeDelta::milliseconds(250));
+        let t = Local::now();
+        for offset in [0, 100, 220, 300, 410, 500] {
+            plain.insert(t + TimeDelta::milliseconds(offset)).await.unwrap();
+            paused.insert(t + TimeDelta::milliseconds(offset)).await.unwrap();
+        }
+        let deadline = plain.effective_deadline(8.).await.unwrap();
+        let paused_deadline = paused.effective_deadline(8.).await.unwrap();
+        assert_eq!(TimeDelta::milliseconds(250), paused_deadline - deadline);
+        assert!(deadline > plain.last_arrived_at().await.unwrap());
+
+        // phi crosses the target at the deadline
+        let before = plain.phi(deadline - TimeDelta::milliseconds(1)).await.unwrap();
+        let after = plain.phi(deadline + TimeDelta::milliseconds(1)).await.unwrap();
+        assert!(before < 8. && after >= 8., "{} {}", before, after);
+
+        let err = plain.time_to_phi(0.).await.unwrap_err();
+        assert_eq!(Some(&PhiError::UnreachablePhi(0.)), err.downcast_ref::<PhiError>());
+    }
+
+    #[tokio::test]
+    async fn test_phi_after_increases_with_offset() {
+        let detector = Detector::new(10);
+        let t = Local::now();
+        for offset in [0, 100, 220, 300, 410, 500] {
+            detector.insert(t + TimeDelta::milliseconds(offset)).await.unwrap();
+        }
+        let history = detector.interval_history().await;
+        let mut previous = 0.;
+        for extra in [0, 50, 100, 150, 200, 400, 10_000] {
+            let phi = detector.phi_after(TimeDelta::milliseconds(extra)).await.unwrap();
+            assert_eq!(detector.phi(t + TimeDelta::milliseconds(500 + extra)).await.unwrap(), phi);
+            assert!(phi >= previous, "{} < {} at {}ms", phi, previous, extra);
+            previous = phi;
+        }
+        assert_eq!(f64::INFINITY, previous);
+        assert_eq!(history, detector.interval_history().await);
+
+        let huge = TimeDelta::milliseconds(i64::MAX);
+        assert_eq!(f64::INFINITY, detector.phi_after(huge).await.unwrap());
+        assert!(detector.phi_after(-huge).await.unwrap() < 1e-6);
+        let capped = Detector::builder(10).with_max_phi(30.).build();
+        capped.insert(t).await.unwrap();
+        capped.insert(t + TimeDelta::milliseconds(100)).await.unwrap();
+        assert_eq!(30., capped.phi_after(huge).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_min_interval_keeps_duplicates_out() {
+        let plain = Detector::new(20);
+        let dropping = Detector::builder(20).with_min_interval(1).build();
+        let coalescing = Detector::builder(20)
+            .with_min_interval(10)
+            .with_min_interval_policy(MinIntervalPolicy::Coalesce)
+            .build();
+        let t = Local::now();
+        for i in 0..5 {
+            // every heartbeat delivered twice
+            for _ in 0..2 {
+                let arrived_at = t + TimeDelta::milliseconds(i * 100);
+                plain.insert(arrived_at).await.unwrap();
+                dropping.insert(arrived_at).await.unwrap();
+                coalescing.insert(arrived_at).await.unwrap();
+            }
+        }
+        // a burst 5ms after the last heartbeat moves the coalesced arrival
+        coalescing.insert(t + TimeDelta::milliseconds(405)).await.unwrap();
+
+        let (_, plain_mean) = plain.statistics.read().await.variance_and_mean();
+        assert_eq!(400. / 9., plain_mean);
+        let (_, mean) = dropping.statistics.read().await.variance_and_mean();
+        assert_eq!(100., mean);
+        assert_eq!(t + TimeDelta::milliseconds(400), dropping.last_arrived_at().await.unwrap());
+        let (_, mean) = coalescing.statistics.read().await.variance_and_mean();
+        assert_eq!(100., mean);
+        assert_eq!(t + TimeDelta::milliseconds(405), coalescing.last_arrived_at().await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_default_matches_new_1000() {
+        let default = Detector::default();
+        let new = Detector::new(1000);
+        assert_eq!(1000, default.statistics.read().await.window_length);
+        assert_eq!(new.acceptable_pause(), default.acceptable_pause());
+        let t = Local::now();
+        for offset in [0, 100, 220, 300, 410] {
+            default.insert(t + TimeDelta::milliseconds(offset)).await.unwrap();
+            new.insert(t + TimeDelta::milliseconds(offset)).await.unwrap();
+        }
+        let query_at = t + TimeDelta::milliseconds(600);
+        assert_eq!(new.phi(query_at).await.unwrap(), default.phi(query_at).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_from_intervals() {
+        let t = Local::now();
+        let detector = Detector::from_intervals(10, vec![100, 200, 150, 350], t).unwrap();
+        let (_, mean) = detector.statistics.read().await.variance_and_mean();
+        assert_eq!(200., mean);
+        assert_eq!(t, detector.last_arrived_at().await.unwrap());
+        assert_eq!(t - TimeDelta::milliseconds(350), detector.interval_history().await[2].0);
+
+        // behaves like the detector the intervals were replayed into
+        let replayed = Detector::new(10);
+        for offset in [-800, -700, -500, -350, 0] {
+            replayed.insert(t + TimeDelta::milliseconds(offset)).await.unwrap();
+        }
+        let query_at = t + TimeDelta::milliseconds(300);
+        assert_eq!(replayed.phi(query_at).await.unwrap(), detector.phi(query_at).await.unwrap());
+
+        let err = Detector::from_intervals(2, vec![100, 200, 300], t).unwrap_err();
+        assert!(matches!(err.downcast_ref::<PhiError>(), Some(PhiError::InvalidConfig(_))));
+        for interval in [u64::MAX, i64::MAX as u64 / 1000] {
+            let err = Detector::from_intervals(10, vec![100, interval], t).unwrap_err();
+            assert_eq!(Some(&PhiError::IntervalOutOfRange(interval)), err.downcast_ref::<PhiError>());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_microsecond_intervals_keep_sub_ms_variance() {
+        let millis = Detector::new(20);
+        let micros = Detector::builder(20).with_interval_unit(IntervalUnit::Microseconds).build();
+        let t = Local::now();
+        let mut offset = 0;
+        for interval in [0, 300, 700, 400, 600, 350, 650] {
+            offset += interval;
+            millis.insert(t + TimeDelta::microseconds(offset)).await.unwrap();
+            micros.insert(t + TimeDelta::microseconds(offset)).await.unwrap();
+        }
+        let millis_status = millis.status(t + TimeDelta::microseconds(offset)).await.unwrap();
+        let micros_status = micros.status(t + TimeDelta::microseconds(offset)).await.unwrap();
+        // every interval truncates to 0ms, leaving only the floor
+        assert_eq!(0., millis.statistics.read().await.variance_and_mean().0);
+        assert_eq!(1., millis_status.std);
+        assert!((micros_status.mean - 0.5).abs() < 1e-9, "{}", micros_status.mean);
+        assert!(micros_status.std > 0.1, "{}", micros_status.std);
+
+        // a millisecond and a half late is suspicious with microseconds, not with milliseconds
+        let late = t + TimeDelta::microseconds(offset + 2000);
+        assert!(micros.is_suspected(late).await.unwrap());
+        assert!(!millis.is_suspected(late).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_on_insert_receives_intervals() {
+        let seen = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let sink = Arc::clone(&seen);
+        let detector = Detector::builder(10)
+            .with_on_insert(move |interval, arrived_at| sink.lock().unwrap().push((interval, arrived_at)))
+            .build();
+        let t = Local::now();
+        for offset in [0, 100, 250, 200, 600] {
+            detector.insert(t + TimeDelta::milliseconds(offset)).await.unwrap();
+        }
+        detector.insert_interval(50).await.unwrap();
+        // nothing for the first heartbeat or the one going backwards
+        let expected = vec![
+            (100, t + TimeDelta::milliseconds(100)),
+            (150, t + TimeDelta::milliseconds(250)),
+            (350, t + TimeDelta::milliseconds(600)),
+            (50, t + TimeDelta::milliseconds(650)),
+        ];
+        assert_eq!(expected, *seen.lock().unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_variance_stable_for_large_near_constant_intervals() {
+        let detector = Detector::builder(100).with_min_std_deviation(TimeDelta::zero()).build();
+        let ewma = Detector::with_ewma(100, 0.1);
+        for i in 0..100 {
+            let interval = 10_000_000 + i % 2;
+            detector.insert_interval(interval).await.unwrap();
+            ewma.insert_interval(interval).await.unwrap();
+        }
+        let (variance, _) = detector.statistics.read().await.variance_and_mean();
+        assert!(variance >= 0. && (variance - 0.25).abs() < 1e-6, "{}", variance);
+        let (variance, _) = ewma.statistics.read().await.variance_and_mean();
+        assert!(variance >= 0., "{}", variance);
+        let status = detector.status(detector.last_arrived_at().await.unwrap()).await.unwrap();
+        assert!(status.std.is_finite());
+
+        assert!(!phi_from_params(120., 100., -1e-12, PhiBase::Log10).is_nan());
+    }
+
+    #[tokio::test]
+    async fn test_network_offset_lowers_mean() {
+        let plain = Detector::new(10);
+        let offset = Detector::builder(10).with_network_offset(TimeDelta::milliseconds(30)).build();
+        let t = Local::now();
+        for ms in [0, 100, 220, 300, 410, 480] {
+            plain.insert(t + TimeDelta::milliseconds(ms)).await.unwrap();
+            offset.insert(t + TimeDelta::milliseconds(ms)).await.unwrap();
+        }
+        let (_, plain_mean) = plain.statistics.read().await.variance_and_mean();
+        let (_, mean) = offset.statistics.read().await.variance_and_mean();
+        assert_eq!(96., plain_mean);
+        assert_eq!(66., mean);
+        assert_eq!(plain.last_arrived_at().await.unwrap(), offset.last_arrived_at().await.unwrap());
+
+        // a 10ms interval is clamped to 0 rather than going negative
+        offset.insert(t + TimeDelta::milliseconds(490)).await.unwrap();
+        assert_eq!(Some(0), offset.min_interval_ms().await);
+    }
+
+    #[tokio::test]
+    async fn test_panicking_on_insert_leaves_detector_usable() {
+        let detector = Arc::new(Detector::builder(10)
+            .with_on_insert(|interval, _| assert!(interval < 500, "slow heartbeat"))
+            .build());
+        let t = Local::now();
+        detector.insert(t).await.unwrap();
+        detector.insert(t + TimeDelta::milliseconds(100)).await.unwrap();
+
+        let panicking = Arc::clone(&detector);
+        let joined = tokio::spawn(async move {
+            panicking.insert(t + TimeDelta::milliseconds(700)).await.unwrap();
+        }).await;
+        assert!(joined.unwrap_err().is_panic());
+
+        // the lock was released and the interval recorded before the callback ran
+        assert_eq!(vec![100, 600], detector.interval_history().await.into_iter().map(|(_, v)| v).collect::<Vec<_>>());
+        detector.insert(t + TimeDelta::milliseconds(800)).await.unwrap();
+        assert!(detector.phi(t + TimeDelta::milliseconds(900)).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_phi_with_snapshot() {
+        let detector = Detector::with_acceptable_pause(10, TimeDelta::milliseconds(20));
+        let t = Local::now();
+        for ms in [0, 100, 220, 300, 410, 480] {
+            detector.insert(t + TimeDelta::milliseconds(ms)).await.unwrap();
+        }
+        let snapshot = detector.stats_snapshot().await;
+        assert_eq!(96., snapshot.mean);
+        let last_arrived_at = detector.last_arrived_at().await.unwrap();
+        for elapsed in 0..100 {
+            let elapsed = elapsed * 3;
+            let expected = detector.phi(last_arrived_at + TimeDelta::milliseconds(elapsed)).await.unwrap();
+            assert_eq!(expected, detector.phi_with_snapshot(&snapshot, elapsed as f64));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_auto_window_converges_on_stationary_intervals() {
+        use rand::{Rng, SeedableRng};
+        let mut rng = rand::rngs::StdRng::seed_from_u64(7);
+        let detector = Detector::builder(8).with_auto_window(4096, 0.1).build();
+        assert_eq!(8, detector.window_length());
+        for _ in 0..2000 {
+            detector.insert_interval(rng.random_range(50..150)).await.unwrap();
+        }
+        let converged = detector.window_length();
+        assert!(converged > 8 && converged < 4096, "{}", converged);
+        assert_eq!(detector.statistics.read().await.window_length, converged);
+        for _ in 0..2000 {
+            detector.insert_interval(rng.random_range(50..150)).await.unwrap();
+        }
+        assert_eq!(converged, detector.window_length());
+    }
+
+    #[tokio::test]
+    async fn test_time_since_last() {
+        let detector = Detector::new(10);
+        let t = Local::now();
+        let err = detector.time_since_last(t).unwrap_err();
+        assert_eq!(Some(&PhiError::NotEnoughSamples), err.downcast_ref::<PhiError>());
+        assert!(detector.elapsed_ms(t).is_err());
+
+        detector.insert(t).await.unwrap();
+        let gap = TimeDelta::milliseconds(1234);
+        assert_eq!(gap, detector.time_since_last(t + gap).unwrap());
+        assert_eq!(1234., detector.elapsed_ms(t + gap).unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_arrivals_past_2262_are_cached() {
+        let t = NaiveDate::from_ymd_opt(2300, 1, 1).unwrap().and_hms_opt(0, 0, 0).unwrap().and_local_timezone(Local).unwrap();
+        let detector = Detector::new(10);
+        for ms in [0, 100, 220, 300] {
+            detector.insert(t + TimeDelta::milliseconds(ms)).await.unwrap();
+        }
+        let last = t + TimeDelta::milliseconds(300);
+        assert_eq!(TimeDelta::milliseconds(150), detector.time_since_last(last + TimeDelta::milliseconds(150)).unwrap());
+        let phi = detector.phi_after(TimeDelta::milliseconds(150)).await.unwrap();
+        assert!(phi > 1., "{}", phi);
+        assert_eq!(detector.phi(last + TimeDelta::milliseconds(150)).await.unwrap(), phi);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_insert_batching_loses_nothing() {
+        let detector = Arc::new(Detector::builder(2000).with_insert_batching().build());
+        let t = Local::now();
+        // with the lock held elsewhere every insert is queued rather than applied
+        let held = detector.statistics.write().await;
+        let inserts = (0..1000).map(|i| {
+            let detector = Arc::clone(&detector);
+            tokio::spawn(async move {
+                detector.insert(t + Duration::milliseconds(i * 10)).await.unwrap();
+            })
+        });
+        for insert in futures::future::join_all(inserts).await {
+            insert.unwrap();
+        }
+        assert!(detector.cache.load().is_none());
+        drop(held);
+        detector.flush().await;
+
+        let history = detector.interval_history().await;
+        assert_eq!(999, history.len());
+        assert!(history.iter().all(|(_, interval)| *interval == 10));
+        assert_eq!(0, detector.clock_regression_count().await);
+        assert_eq!(t + Duration::milliseconds(9990), detector.last_arrived_at().await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_raw_phi_ignores_pause() {
+        let detector = Detector::with_acceptable_pause(10, TimeDelta::milliseconds(500));
+        let t = Local::now();
+        for i in 0..5 {
+            detector.insert(t + Duration::milliseconds(i * 100 + i % 2 * 10)).await.unwrap();
+        }
+        for ms in [400, 450, 600, 900] {
+            let query_at = t + Duration::milliseconds(ms);
+            let phi = detector.phi(query_at).await.unwrap();
+            let raw_phi = detector.raw_phi(query_at).unwrap();
+            assert!(raw_phi >= phi, "raw phi {} below phi {} at {}ms", raw_phi, phi, ms);
+        }
+        let query_at = t + Duration::milliseconds(600);
+        assert!(detector.raw_phi(query_at).unwrap() > detector.phi(query_at).await.unwrap());
+        assert!(matches!(
+            Detector::new(10).raw_phi(t).unwrap_err().downcast_ref::<PhiError>(),
+            Some(PhiError::NotEnoughSamples)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_rate_change_detected() {
+        let t = Local::now();
+        let heartbeats: Vec<_> = (0..20).map(|i| t + Duration::milliseconds(i * 1000))
+            .chain((1..=5).map(|i| t + Duration::milliseconds(19_000 + i * 5000)))
+            .collect();
+        for reset in [false, true] {
+            let changes = Arc::new(std::sync::Mutex::new(Vec::<RateChange>::new()));
+            let seen = Arc::clone(&changes);
+            let detector = Detector::builder(100)
+                .with_rate_change_detection(2., 3, reset)
+                .with_on_rate_change(move |change| seen.lock().unwrap().push(change))
+                .build();
+            for arrived_at in &heartbeats {
+                detector.insert(*arrived_at).await.unwrap();
+            }
+
+            let changes = changes.lock().unwrap().clone();
+            // the third 5s interval trips the detection, the rest then make up the new window
+            // or diverge from the mixed one again
+            let change = changes[0];
+            assert_eq!(1000., change.window_mean);
+            assert_eq!(5000., change.recent_mean);
+            assert_eq!(t + Duration::milliseconds(34_000), change.detected_at);
+            assert_eq!(reset, change.reset);
+            let window = detector.interval_history().await.len();
+            if reset {
+                assert_eq!(1, changes.len());
+                assert_eq!(5, window);
+                assert_eq!(5000, detector.min_interval_ms().await.unwrap());
+            } else {
+                assert_eq!(24, window);
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_severity_boundaries() {
+        let t = Local::now();
+        let query_at = t + Duration::milliseconds(540);
+        let severity = |warning_phi, critical_phi| async move {
+            let detector = Detector::builder(10).with_severity_thresholds(warning_phi, critical_phi).build();
+            for i in 0..5 {
+                detector.insert(t + Duration::milliseconds(i * 100 + i % 2 * 20)).await.unwrap();
+            }
+            detector.severity(query_at).await.unwrap()
+        };
+        let phi = {
+            let detector = Detector::new(10);
+            for i in 0..5 {
+                detector.insert(t + Duration::milliseconds(i * 100 + i % 2 * 20)).await.unwrap();
+            }
+            detector.phi(query_at).await.unwrap()
+        };
+        assert!(phi > 0.1);
+
+        // both thresholds count as Warning
+        assert_eq!(Severity::Warning, severity(phi, phi + 1.).await);
+        assert_eq!(Severity::Healthy, severity(phi + 0.01, phi + 1.).await);
+        assert_eq!(Severity::Warning, severity(0., phi).await);
+        assert_eq!(Severity::Critical, severity(0., phi - 0.01).await);
+        assert!(Detector::new(10).severity(t).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_insert_with_send_time() {
+        let detector = Detector::new(10);
+        assert_eq!(None, detector.one_way_delay());
+        let t = Local::now();
+        // the sender's clock runs 3s ahead and deliveries take 10 to 50ms
+        let skew = Duration::milliseconds(3000);
+        let mut received = t;
+        for (i, delay) in [10, 50, 20, 40, 10, 30].into_iter().enumerate() {
+            let sent = t + skew + Duration::milliseconds(i as i64 * 1000);
+            received = sent - skew + Duration::milliseconds(delay);
+            detector.insert_with_send_time(sent, received).await.unwrap();
+        }
+
+        assert!(detector.interval_history().await.iter().all(|(_, interval)| *interval == 1000));
+        assert_eq!(Some(Duration::milliseconds(30) - skew), detector.one_way_delay());
+        // elapsed time is measured from the receipt of the last heartbeat
+        let metronome = Detector::new(10);
+        for i in 0..6 {
+            metronome.insert(t + Duration::milliseconds(i * 1000)).await.unwrap();
+        }
+        let elapsed = Duration::milliseconds(1200);
+        assert_eq!(
+            metronome.phi(t + Duration::milliseconds(5000) + elapsed).await.unwrap(),
+            detector.phi(received + elapsed).await.unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_dropped_duplicate_keeps_one_way_delay() {
+        let detector = Detector::builder(10).with_min_interval(100).build();
+        let t = Local::now();
+        detector.insert_with_send_time(t, t + Duration::milliseconds(10)).await.unwrap();
+        let sent = t + Duration::milliseconds(1000);
+        detector.insert_with_send_time(sent, sent + Duration::milliseconds(20)).await.unwrap();
+        // a redelivery of the last heartbeat is a short interval, dropped along with its delay
+        detector.insert_with_send_time(sent, sent + Duration::milliseconds(500)).await.unwrap();
+        assert_eq!(Some(Duration::milliseconds(20)), detector.one_way_delay());
+        assert_eq!(vec![1000], detector.with_intervals(|intervals| intervals.to_vec()).await);
+    }
+
+    #[tokio::test]
+    async fn test_multi_day_outage() {
+        let t = Local::now();
+        let capped = Detector::builder(10).with_max_phi(100.).build();
+        let uncapped = Detector::builder(10).with_interval_unit(IntervalUnit::Microseconds).build();
+        for detector in [&capped, &uncapped] {
+            for i in 0..5 {
+                detector.insert(t + Duration::milliseconds(i * 1000 + i % 2 * 50)).await.unwrap();
+            }
+        }
+
+        for outage in [Duration::days(2), Duration::days(365 * 1000)] {
+            let query_at = t + Duration::milliseconds(4000) + outage;
+            assert_eq!(100., capped.phi(query_at).await.unwrap());
+            assert_eq!(f64::INFINITY, uncapped.phi(query_at).await.unwrap());
+            assert_eq!(f64::INFINITY, uncapped.raw_phi(query_at).unwrap());
+        }
+        assert!(capped.is_suspected(t + Duration::days(2)).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_coefficient_of_variation() {
+        let t = Local::now();
+        let steady = Detector::new(10);
+        let jittery = Detector::new(10);
+        let slow_jittery = Detector::new(10);
+        assert!(steady.coefficient_of_variation().await.is_err());
+        for i in 0..9 {
+            steady.insert(t + Duration::milliseconds(i * 100)).await.unwrap();
+            jittery.insert(t + Duration::milliseconds(i * 100 + i % 2 * 30)).await.unwrap();
+            // the same relative jitter at a tenth of the rate
+            slow_jittery.insert(t + Duration::milliseconds(i * 1000 + i % 2 * 300)).await.unwrap();
+        }
+
+        assert_eq!(0., steady.coefficient_of_variation().await.unwrap());
+        let cv = jittery.coefficient_of_variation().await.unwrap();
+        assert!((cv - 0.3).abs() < 1e-9, "{}", cv);
+        assert!((slow_jittery.coefficient_of_variation().await.unwrap() - cv).abs() < 1e-9);
+    }
+
+    #[tokio::test]
+    async fn test_phi_with_params() {
+        let detector = Detector::with_acceptable_pause(10, TimeDelta::milliseconds(50));
+        let t = Local::now();
+        assert!(detector.phi_with_params(t, 100., 400.).is_err());
+        for i in 0..6 {
+            detector.insert(t + Duration::milliseconds(i * 100 + i % 2 * 20)).await.unwrap();
+        }
+
+        let snapshot = detector.stats_snapshot().await;
+        for ms in [500, 600, 700, 800] {
+            let query_at = t + Duration::milliseconds(ms);
+            assert_eq!(
+                detector.phi(query_at).await.unwrap(),
+                detector.phi_with_params(query_at, snapshot.mean, snapshot.variance).unwrap()
+            );
+        }
+        // a wider external fit is more lenient at the same time
+        let query_at = t + Duration::milliseconds(700);
+        assert!(detector.phi_with_params(query_at, snapshot.mean, 4. * snapshot.variance).unwrap()
+            < detector.phi(query_at).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_export_intervals_csv() {
+        let detector = Detector::new(10);
+        let t = Local::now();
+        for i in 0..6 {
+            detector.insert(t + Duration::milliseconds(i * 100 + i % 2 * 20)).await.unwrap();
+        }
+
+        let mut csv = Vec::new();
+        detector.export_intervals_csv(&mut csv).await.unwrap();
+        let csv = String::from_utf8(csv).unwrap();
+        let mut lines = csv.lines();
+        assert_eq!(Some("index,arrived_at,interval_ms"), lines.next());
+        let rows: Vec<&str> = lines.collect();
+        assert_eq!(detector.phi_detailed(t).await.unwrap().sample_count, rows.len());
+        let arrived_at = (t + Duration::milliseconds(120)).to_rfc3339();
+        assert_eq!(format!("0,{},120", arrived_at), rows[0]);
+        assert!(rows[4].starts_with("4,") && rows[4].ends_with(",120"));
+    }
+
+    #[test]
+    fn test_statistics_accessors() {
+        let mut stats = Statistics::new(3);
+        assert_eq!(3, stats.window_length());
+        assert!(stats.is_empty());
+        assert_eq!(None, stats.last_arrived_at());
+        let t = Local::now();
+        for ms in [0, 100, 250, 300, 420] {
+            stats.insert(t + Duration::milliseconds(ms));
+        }
+
+        assert_eq!(3, stats.len());
+        assert!(!stats.is_empty());
+        assert_eq!(Some(t + Duration::milliseconds(420)), stats.last_arrived_at());
+        assert_eq!(vec![150, 50, 120], stats.intervals().collect::<Vec<_>>());
+        let restored = stats.clone();
+        assert!(stats.intervals().eq(restored.intervals()));
+    }
+
+    #[tokio::test]
+    async fn test_hysteresis_holds_state_in_deadband() {
+        let detector = Detector::builder(10).with_hysteresis(8., 4.).build();
+        let flapping = Detector::builder(10).with_threshold(6.).build();
+        let phis = [2., 6.5, 5.5, 7., 8.5, 7., 5., 6.5, 4.5, 3.9, 5., 7.9, 6.5, 8.1, 4.];
+        let verdicts: Vec<bool> = phis.iter().map(|phi| detector.verdict(*phi).1).collect();
+        assert_eq!(vec![
+            false, false, false, false, true, true, true, true, true, false, false, false, false, true, true,
+        ], verdicts);
+        let flaps = |verdicts: &[bool]| verdicts.windows(2).filter(|w| w[0] != w[1]).count();
+        assert_eq!(3, flaps(&verdicts));
+        let plain: Vec<bool> = phis.iter().map(|phi| flapping.verdict(*phi).1).collect();
+        assert!(flaps(&plain) > 6);
+
+        let t = Local::now();
+        for i in 0..5 {
+            detector.insert(t + Duration::milliseconds(i * 100 + i % 2 * 10)).await.unwrap();
+        }
+        assert!(detector.is_suspected(t + Duration::milliseconds(2000)).await.unwrap());
+        assert!(!detector.is_suspected(t + Duration::milliseconds(400)).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_phi_against_next_expected_arrival() {
+        let standard = Detector::new(10);
+        let expected = Detector::builder(10).with_phi_reference(PhiReference::NextExpected).build();
+        let t = Local::now();
+        for detector in [&standard, &expected] {
+            for i in 0..5 {
+                detector.insert(t + Duration::milliseconds(i * 100 + i % 2 * 20)).await.unwrap();
+            }
+        }
+
+        // the mean is 100ms, so the next heartbeat is expected at 500ms
+        for ms in [400, 450, 480, 500] {
+            let query_at = t + Duration::milliseconds(ms);
+            assert_eq!(0., expected.phi(query_at).await.unwrap());
+            assert!(standard.phi(query_at).await.unwrap() > 0.);
+        }
+        let mut previous = 0.;
+        for ms in [510, 530, 560, 600] {
+            let query_at = t + Duration::milliseconds(ms);
+            let phi = expected.phi(query_at).await.unwrap();
+            assert!(phi > previous, "{} at {}ms", phi, ms);
+            let shifted = standard.phi(query_at).await.unwrap() - 2f64.log10();
+            assert!((phi - shifted).abs() < 1e-9);
+            previous = phi;
+        }
+        let to_phi = expected.time_to_phi(1.).await.unwrap();
+        let deadline = t + Duration::milliseconds(400) + to_phi;
+        // elapsed time is in whole ms, so phi crosses 1 within the ms after the deadline
+        assert!(expected.phi(deadline).await.unwrap() < 1.);
+        assert!(expected.phi(deadline + Duration::milliseconds(1)).await.unwrap() >= 1.);
+    }
+
+    #[test]
+    fn test_build_checked_rejects_pathological_configs() {
+        let rejects = |builder: DetectorBuilder, reason: &str| {
+            let err = builder.build_checked().unwrap_err();
+            match err.downcast_ref::<PhiError>() {
+                Some(PhiError::InvalidConfig(message)) => assert!(message.contains(reason), "{}", message),
+                other => panic!("expected InvalidConfig, got {:?}", other),
+            }
+        };
+        rejects(Detector::builder(0), "window_length");
+        rejects(Detector::builder(10).with_ewma(1.5), "alpha");
+        rejects(Detector::builder(10).with_acceptable_pause(TimeDelta::milliseconds(-1)), "negative");
+        rejects(Detector::builder(10).with_min_std_deviation(TimeDelta::zero()), "min std deviation");
+        rejects(Detector::builder(10).with_threshold(f64::NAN), "threshold");
+        rejects(Detector::builder(10).with_trimmed_fraction(0.5), "trimmed fraction");
+        rejects(
+            Detector::builder(10).with_prior(100., 10.).with_acceptable_pause(TimeDelta::seconds(20)),
+            "over 100 times",
+        );
+        rejects(
+            Detector::builder(10).with_prior(100., 10.).with_min_std_deviation(TimeDelta::milliseconds(200)),
+            "over the expected interval",
+        );
+
+        assert!(Detector::builder(10).build_checked().is_ok());
+        assert!(Detector::builder(10)
+            .with_prior(100., 10.)
+            .with_acceptable_pause(TimeDelta::seconds(1))
+            .with_min_std_deviation(TimeDelta::milliseconds(5))
+            .build_checked()
+            .is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_replay_csv() {
+        let csv = "2024-05-01T12:00:00.000+00:00
+2024-05-01T12:00:00.100+00:00
+
+2024-05-01T12:00:00.220+00:00
+2024-05-01T14:00:00.300+02:00
+";
+        let detector = Detector::new(10);
+        detector.replay_csv(csv.as_bytes()).await.unwrap();
+        let report = detector.phi_detailed(Local::now()).await.unwrap();
+        assert_eq!(3, report.sample_count);
+        assert_eq!(100., report.mean);
+
+        let malformed = "2024-05-01T12:00:00.000+00:00\nnot a time\n";
+        let err = detector.replay_csv(malformed.as_bytes()).await.unwrap_err();
+        assert!(matches!(err.downcast_ref::<PhiError>(), Some(PhiError::MalformedCsv(2, _))));
+        assert!(err.to_string().contains("line 2"));
+        assert_eq!(3, detector.phi_detailed(Local::now()).await.unwrap().sample_count);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_watch_resolves_after_heartbeats_stop() {
+        let detector = Arc::new(Detector::with_acceptable_pause(10, TimeDelta::milliseconds(200)));
+        detector.insert_now().await.unwrap();
+        let watch = tokio::spawn({
+            let detector = Arc::clone(&detector);
+            async move { detector.watch(3.).await }
+        });
+        for _ in 0..10 {
+            tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+            detector.insert_now().await.unwrap();
+        }
+        assert!(!watch.is_finished());
+
+        let stopped = Local::now();
+        tokio::time::timeout(std::time::Duration::from_secs(5), watch).await.unwrap().unwrap();
+        let waited = Local::now() - stopped;
+        let expected = detector.time_to_phi(3.).await.unwrap();
+        assert!(waited >= expected - Duration::milliseconds(20), "{} before {}", waited, expected);
+        assert!(detector.phi(Local::now()).await.unwrap() >= 3.);
+    }
+
+    #[tokio::test]
+    async fn test_cdf_value() {
+        let t = Local::now();
+        // mean 100ms and std 20ms
+        let detector = Detector::from_intervals(10, vec![80, 120, 80, 120], t).unwrap();
+        // standard normal CDF at 1 and -0.5
+        let cdf = detector.cdf_value(t + Duration::milliseconds(120)).await.unwrap();
+        assert!((cdf - 0.841_344_746_068_542_9).abs() < 1e-12, "{}", cdf);
+        let cdf = detector.cdf_value(t + Duration::milliseconds(90)).await.unwrap();
+        assert!((cdf - 0.308_537_538_725_986_9).abs() < 1e-12, "{}", cdf);
+        let phi = detector.phi(t + Duration::milliseconds(90)).await.unwrap();
+        assert!((phi + (1. - cdf).log10()).abs() < 1e-12);
+        assert!(Detector::new(10).cdf_value(t).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_failure_probability_follows_phi() {
+        let t = Local::now();
+        let student = Detector::builder(10).with_student_t_tail(30).build();
+        let missed = Detector::builder(10).with_phi_base(PhiBase::Ln).build();
+        for ms in [0, 100, 200, 310, 390, 500] {
+            student.insert(t + Duration::milliseconds(ms)).await.unwrap();
+            missed.insert(t + Duration::milliseconds(ms)).await.unwrap();
+        }
+        missed.report_missed_heartbeats(2);
+        // the t tail is much heavier than the normal one with 5 samples
+        assert!(student.failure_probability(t + Duration::milliseconds(630)).await.unwrap() > 1e-2);
+        for detector in [&student, &missed] {
+            for elapsed in [130, 200, 500] {
+                let query_at = t + Duration::milliseconds(500 + elapsed);
+                let phi = detector.phi(query_at).await.unwrap();
+                let p = detector.failure_probability(query_at).await.unwrap();
+                assert!((detector.config.base.neg_log(p) - phi).abs() < 1e-9 || p == 0., "{} {}", phi, p);
+                let suspected = detector.is_suspected(query_at).await.unwrap();
+                assert_eq!(suspected, detector.is_suspected_prob(query_at).await.unwrap());
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_window_length_one() {
+        let detector = Detector::new(1);
+        let t = Local::now();
+        for ms in [0, 80, 180, 280] {
+            detector.insert(t + Duration::milliseconds(ms)).await.unwrap();
+        }
+
+        // only the last interval of 100ms is kept, with a std floored at 1ms
+        assert_eq!(vec![100], detector.with_intervals(|intervals| intervals.to_vec()).await);
+        let report = detector.phi_detailed(t + Duration::milliseconds(380)).await.unwrap();
+        assert_eq!((100., 1.), (report.mean, report.variance));
+        let mut previous = detector.phi(t + Duration::milliseconds(350)).await.unwrap();
+        for ms in [380, 381, 382, 383, 385] {
+            let phi = detector.phi(t + Duration::milliseconds(ms)).await.unwrap();
+            assert!(phi > previous, "{} not above {} at {}ms", phi, previous, ms);
+            previous = phi;
+        }
+    }
+
+    #[tokio::test]
+    async fn test_config_getters() {
+        let detector = Detector::with_acceptable_pause(250, TimeDelta::milliseconds(1500));
+        assert_eq!(250, detector.window_length());
+        assert_eq!(TimeDelta::milliseconds(1500), detector.acceptable_pause());
+        let default = Detector::default();
+        assert_eq!(crate::DEFAULT_WINDOW_LENGTH, default.window_length());
+        assert_eq!(TimeDelta::zero(), default.acceptable_pause());
+    }
+
+    #[tokio::test]
+    async fn test_insert_returns_interval() {
+        let detector = Detector::new(10);
+        let t = Local::now();
+        assert_eq!(None, detector.insert(t).await.unwrap());
+        assert_eq!(Some(250), detector.insert(t + Duration::milliseconds(250)).await.unwrap());
+        // a heartbeat older than the last one isn't recorded
+        assert_eq!(None, detector.insert(t).await.unwrap());
+        let micros = Detector::builder(10).with_interval_unit(IntervalUnit::Microseconds).build();
+        micros.insert(t).await.unwrap();
+        assert_eq!(Some(1500), micros.insert(t + Duration::microseconds(1500)).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_idle_decay_forgives_long_idle() {
+        let t = Local::now();
+        let strict = Detector::builder(10).with_min_std_deviation(TimeDelta::milliseconds(100)).build();
+        let decaying = Detector::builder(10)
+            .with_min_std_deviation(TimeDelta::milliseconds(100))
+            .with_idle_decay(100., 3.)
+            .build();
+        for detector in [&strict, &decaying] {
+            for i in 0..6 {
+                detector.insert(t + Duration::milliseconds(i * 1000)).await.unwrap();
+            }
+        }
+
+        let last = t + Duration::milliseconds(5000);
+        // up to 3 means of silence both agree
+        for ms in [500, 1500, 3000] {
+            let query_at = last + Duration::milliseconds(ms);
+            assert_eq!(strict.phi(query_at).await.unwrap(), decaying.phi(query_at).await.unwrap());
+        }
+        let query_at = last + Duration::milliseconds(4000);
+        let strict_phi = strict.phi(query_at).await.unwrap();
+        let decaying_phi = decaying.phi(query_at).await.unwrap();
+        assert!(strict_phi > 8., "{}", strict_phi);
+        assert!(decaying_phi < 8., "{}", decaying_phi);
+
+        // a fresh heartbeat ends the decay, both are 1.5s past a heartbeat on the same window
+        decaying.insert(last + Duration::milliseconds(1000)).await.unwrap();
+        let strict_phi = strict.phi(last + Duration::milliseconds(1500)).await.unwrap();
+        assert_eq!(strict_phi, decaying.phi(last + Duration::milliseconds(2500)).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_trimmed_fraction_resists_outliers() {
+        let t = Local::now();
+        let plain = Detector::new(20);
+        let trimmed = Detector::builder(20).with_trimmed_fraction(0.1).build();
+        let mut arrived_at = t;
+        for i in 0..21 {
+            for detector in [&plain, &trimmed] {
+                detector.insert(arrived_at).await.unwrap();
+            }
+            // a bulk around 100ms with a stall and a burst
+            arrived_at += Duration::milliseconds(match i {
+                4 => 2000,
+                11 => 2,
+                _ => 95 + i % 3 * 5,
+            });
+        }
+
+        let plain = plain.stats_snapshot().await;
+        let trimmed = trimmed.stats_snapshot().await;
+        assert!((trimmed.mean - 100.).abs() < 2., "{}", trimmed.mean);
+        assert!((plain.mean - 100.).abs() > 50., "{}", plain.mean);
+        assert!(trimmed.variance < plain.variance / 100.);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_await_warm() {
+        let detector = Arc::new(Detector::builder(10).with_min_samples(5).build());
+        let t = Local::now();
+        let warm = tokio::spawn({
+            let detector = Arc::clone(&detector);
+            async move { detector.await_warm().await }
+        });
+        for i in 0..5 {
+            detector.insert(t + Duration::milliseconds(i * 100)).await.unwrap();
+            tokio::time::sleep(std::time::Duration::from_millis(5)).await;
+        }
+        // 5 heartbeats are only 4 intervals
+        assert!(!warm.is_finished());
+        assert!(matches!(
+            detector.phi(t).await.unwrap_err().downcast_ref::<PhiError>(),
+            Some(PhiError::NotEnoughSamples)
+        ));
+
+        detector.insert(t + Duration::milliseconds(500)).await.unwrap();
+        tokio::time::timeout(std::time::Duration::from_secs(5), warm).await.unwrap().unwrap();
+        assert!(detector.is_warm());
+        assert!(detector.phi(t + Duration::milliseconds(600)).await.is_ok());
+    }
+
+    /// Fixed-capacity ring buffer.
+    #[derive(Clone, Debug)]
+    struct RingStore {
+        slots: [u64; 4],
+        start: usize,
+        len: usize,
+    }
+
+    impl IntervalStore for RingStore {
+        fn push(&mut self, interval: u64) {
+            assert!(self.len < self.slots.len(), "ring is full");
+            self.slots[(self.start + self.len) % self.slots.len()] = interval;
+            self.len += 1;
+        }
+
+        fn evict_oldest(&mut self) -> Option<u64> {
+            if self.len == 0 {
+                return None;
+            }
+            let oldest = self.slots[self.start];
+            self.start = (self.start + 1) % self.slots.len();
+            self.len -= 1;
+            Some(oldest)
+        }
+
+        fn iter(&self) -> Box<dyn Iterator<Item = u64> + '_> {
+            Box::new((0..self.len).map(|i| self.slots[(self.start + i) % self.slots.len()]))
+        }
+
+        fn len(&self) -> usize {
+            self.len
+        }
+
+        fn clone_store(&self) -> Box<dyn IntervalStore> {
+            Box::new(self.clone())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_custom_interval_store() {
+        let ring = RingStore { slots: [0; 4], start: 0, len: 0 };
+        let custom = Detector::builder(4).with_interval_store(Box::new(ring)).build();
+        let default = Detector::new(4);
+        let t = Local::now();
+        for ms in [0, 100, 210, 300, 420, 500, 590] {
+            custom.insert(t + Duration::milliseconds(ms)).await.unwrap();
+            default.insert(t + Duration::milliseconds(ms)).await.unwrap();
+        }
+
+        assert_eq!(vec![90, 120, 80, 90], custom.with_intervals(|intervals| intervals.to_vec()).await);
+        assert_eq!(default.interval_history().await, custom.interval_history().await);
+        let query_at = t + Duration::milliseconds(700);
+        assert_eq!(default.phi(query_at).await.unwrap(), custom.phi(query_at).await.unwrap());
+        assert_eq!(4, custom.statistics.read().await.len());
+    }
+
+    #[tokio::test]
+    async fn test_debug_output() {
+        let detector = Detector::with_acceptable_pause(12, Duration::milliseconds(50));
+        assert!(format!("{:?}", detector).contains("samples: 0, mean: None"));
+        let t = Local::now();
+        for ms in [0, 100, 200] {
+            detector.insert(t + Duration::milliseconds(ms)).await.unwrap();
+        }
+
+        let debug = format!("{:?}", detector);
+        assert!(debug.contains("window_length: 12"), "{}", debug);
+        assert!(debug.contains("samples: 2, mean: Some(100.0)"), "{}", debug);
+        let _guard = detector.statistics.write().await;
+        assert!(format!("{:?}", detector).contains("statistics: <locked>"));
+    }
+
+    #[tokio::test]
+    async fn test_phi_safe_is_finite() {
+        use rand::Rng;
+        let mut rng = rand::rng();
+        assert_eq!(0., Detector::new(10).phi_safe(Local::now()));
+        for _ in 0..200 {
+            let mut builder = Detector::builder(rng.random_range(1..20))
+                .with_acceptable_pause(Duration::milliseconds(rng.random_range(0..500)));
+            if rng.random_bool(0.3) {
+                builder = builder.with_max_phi(rng.random_range(1. ..20.));
+            }
+            let detector = builder.build();
+            let t = Local::now();
+            let mut arrived_at = t;
+            for _ in 0..rng.random_range(0..10) {
+                // zero intervals make for zero variance
+                arrived_at += Duration::milliseconds(rng.random_range(0..3) * rng.random_range(0..1000));
+                detector.insert(arrived_at).await.unwrap();
+            }
+            for _ in 0..20 {
+                let query_at = t + Duration::milliseconds(rng.random_range(-10_000..10_000_000));
+                let phi = detector.phi_safe(query_at);
+                assert!(phi.is_finite() && phi >= 0., "{} at {} for {:?}", phi, query_at, detector);
+            }
+        }
+
+        let detector = Detector::builder(10).with_max_phi(12.).build();
+        detector.insert(Local::now()).await.unwrap();
+        detector.force_down();
+        assert_eq!(12., detector.phi_safe(Local::now()));
+    }
+
+    #[test]
+    fn test_statistics_with_clock() {
+        let clock = MockClock::new();
+        clock.advance(-3_600_000);
+        let mut stats = Statistics::with_clock(10, &*clock);
+        stats.insert_interval(100);
+        assert_eq!(Some(clock.now() + TimeDelta::milliseconds(100)), stats.last_heartbeat());
+
+        let detector = Detector::builder(10).with_clock(clock.clone()).build_sync();
+        detector.with_statistics_mut(|stats| stats.insert_interval(100)).unwrap();
+        assert_eq!(clock.now() + TimeDelta::milliseconds(100), detector.last_arrived_at().unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_pause_and_resume() {
+        let clock = MockClock::new();
+        let detector = Detector::builder(10).with_clock(clock.clone()).build();
+        for ms in [0, 100, 90, 110, 100] {
+            clock.advance(ms);
+            detector.insert_now().await.unwrap();
+        }
+        let before = detector.stats_snapshot().await;
+
+        detector.pause();
+        clock.advance(60_000);
+        assert_eq!(0., detector.phi_now().await.unwrap());
+        assert_eq!(None, detector.insert_now().await.unwrap());
+        assert_eq!(clock.now(), detector.last_arrived_at().await.unwrap());
+        clock.advance(30_000);
+        assert_eq!(0., detector.phi_now().await.unwrap());
+        // an interval measured elsewhere moves the last arrival as a heartbeat does
+        detector.insert_interval(30_000).await.unwrap();
+        assert_eq!(clock.now(), detector.last_arrived_at().await.unwrap());
+        assert_eq!(before, detector.stats_snapshot().await);
+
+        detector.resume().await;
+        assert!(!detector.is_paused());
+        clock.advance(100);
+        assert_eq!(Some(100), detector.insert_now().await.unwrap());
+        assert_eq!(vec![100, 90, 110, 100, 100], detector.with_intervals(|intervals| intervals.to_vec()).await);
+        // the 90s gap never made it into the window
+        assert!(detector.stats_snapshot().await.variance <= before.variance);
+    }
+
+    #[tokio::test]
+    async fn test_heartbeat_seq() {
+        let detector = Detector::new(3);
+        assert_eq!(0, detector.current_seq().await);
+        let t = Local::now();
+        for i in 1..=10 {
+            detector.insert(t + Duration::milliseconds(i * 100)).await.unwrap();
+            assert_eq!(i as u64, detector.current_seq().await);
+        }
+        // a dropped heartbeat still takes a number
+        detector.insert(t).await.unwrap();
+        assert_eq!(11, detector.current_seq().await);
+        assert_eq!(3, detector.statistics.read().await.len());
+        assert_eq!(11, detector.statistics.read().await.seq());
+        // as do intervals measured elsewhere
+        detector.insert_interval(100).await.unwrap();
+        assert_eq!(12, detector.current_seq().await);
+        let mut stats = Statistics::new(3);
+        stats.insert_interval(100);
+        stats.insert_interval(100);
+        assert_eq!(2, stats.seq());
+    }
+
+    #[tokio::test]
+    async fn test_huge_interval_is_an_error() {
+        let detector = Detector::new(10);
+        detector.insert(Local::now()).await.unwrap();
+        detector.insert_interval(100).await.unwrap();
+        let before = detector.stats_snapshot().await;
+        for interval_ms in [1 << 62, i64::MAX as u64 + 1, u64::MAX] {
+            let err = detector.insert_interval(interval_ms).await.unwrap_err();
+            assert_eq!(Some(&PhiError::IntervalOutOfRange(interval_ms)), err.downcast_ref::<PhiError>());
+        }
+        assert_eq!(before, detector.stats_snapshot().await);
+
+        // Statistics counts it without recording it
+        let mut stats = Statistics::new(3);
+        stats.insert_interval(u64::MAX);
+        assert_eq!((1, 0), (stats.seq(), stats.len()));
+    }
+
+    #[tokio::test]
+    async fn test_late_only_pause_mode() {
+        let pause = TimeDelta::milliseconds(100);
+        let strict = Detector::new(10);
+        let symmetric = Detector::with_acceptable_pause(10, pause);
+        let late_only = Detector::builder(10)
+            .with_acceptable_pause(pause)
+            .with_pause_mode(PauseMode::LateOnly)
+            .build();
+        let t = Local::now();
+        for ms in [0, 100, 220, 300, 410, 500] {
+            for detector in [&strict, &symmetric, &late_only] {
+                detector.insert(t + Duration::milliseconds(ms)).await.unwrap();
+            }
+        }
+        let last = late_only.last_arrived_at().await.unwrap();
+        let phi_at = |detector: &Detector, ms: i64| detector.phi_safe(last + Duration::milliseconds(ms));
+
+        // an early heartbeat: the symmetric pause lowers phi, late only leaves it as without one
+        assert!(phi_at(&symmetric, 80) < phi_at(&strict, 80));
+        assert_eq!(phi_at(&strict, 80), phi_at(&late_only, 80));
+        // overdue, phi waits at its value at the mean for the pause, then both modes agree
+        assert_eq!(phi_at(&late_only, 101), phi_at(&late_only, 150));
+        assert!(phi_at(&late_only, 150) < phi_at(&strict, 150));
+        assert_eq!(phi_at(&symmetric, 400), phi_at(&late_only, 400));
+
+        let early = late_only.time_to_phi(phi_at(&strict, 80)).await.unwrap();
+        assert!(early <= TimeDelta::milliseconds(81), "{}", early);
+        let late = late_only.time_to_phi(phi_at(&strict, 130)).await.unwrap();
+        assert!((late - TimeDelta::milliseconds(230)).abs() <= TimeDelta::milliseconds(1), "{}", late);
+    }
+
+    #[test]
+    fn test_student_t_sf() {
+        // one degree of freedom is the Cauchy distribution, 2.776 the 97.5th percentile for 4
+        assert!((student_t_sf(1., 1.) - 0.25).abs() < 1e-12);
+        assert!((student_t_sf(2.776, 4.) - 0.025).abs() < 1e-4);
+        assert!((student_t_sf(-2.776, 4.) - 0.975).abs() < 1e-4);
+        assert_eq!(0.5, student_t_sf(0., 7.));
+        // many degrees of freedom approach the normal
+        assert!((student_t_sf(2., 10_000.) - (1. - normal_cdf(2., 0., 1.))).abs() < 1e-4);
+    }
+
+    #[tokio::test]
+    async fn test_student_t_tail_for_small_windows() {
+        let normal = Detector::new(10);
+        let student = Detector::builder(10).with_student_t_tail(30).build();
+        let t = Local::now();
+        for ms in [0, 100, 210, 300, 390, 500] {
+            normal.insert(t + Duration::milliseconds(ms)).await.unwrap();
+            student.insert(t + Duration::milliseconds(ms)).await.unwrap();
+        }
+        let at = |ms: i64| t + Duration::milliseconds(500 + ms);
+
+        // 5 intervals of mean 100 and std ~8.9, so 130ms is 3.4 stds out: phi ~1.85 under the
+        // t distribution with 4 degrees of freedom against ~3.4 under the normal
+        let normal_phi = normal.phi(at(130)).await.unwrap();
+        let student_phi = student.phi(at(130)).await.unwrap();
+        assert!((student_phi - 1.85).abs() < 0.01, "{}", student_phi);
+        assert!((normal_phi - 3.4).abs() < 0.01, "{}", normal_phi);
+        assert_eq!(normal.phi(at(100)).await.unwrap(), student.phi(at(100)).await.unwrap());
+        assert!(student.phi(at(140)).await.unwrap() > student_phi);
+
+        // past the crossover both tails are the same
+        let crossed = Detector::builder(10).with_student_t_tail(5).build();
+        for ms in [0, 100, 210, 300, 390, 500] {
+            crossed.insert(t + Duration::milliseconds(ms)).await.unwrap();
+        }
+        assert_eq!(normal_phi, crossed.phi(at(130)).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_acceptable_pause_std() {
+        let with_std = Detector::with_acceptable_pause_std(10, std::time::Duration::from_millis(1500)).unwrap();
+        let with_chrono = Detector::with_acceptable_pause(10, TimeDelta::milliseconds(1500));
+        let t = Local::now();
+        for ms in [0, 100, 190, 300] {
+            with_std.insert(t + Duration::milliseconds(ms)).await.unwrap();
+            with_chrono.insert(t + Duration::milliseconds(ms)).await.unwrap();
+        }
+        assert_eq!(with_chrono.acceptable_pause(), with_std.acceptable_pause());
+        assert_eq!(format!("{:?}", with_chrono), format!("{:?}", with_std));
+        let query_at = t + Duration::milliseconds(2000);
+        assert_eq!(with_chrono.phi(query_at).await.unwrap(), with_std.phi(query_at).await.unwrap());
+
+        let err = Detector::with_acceptable_pause_std(10, std::time::Duration::MAX).unwrap_err();
+        assert!(matches!(err.downcast_ref::<PhiError>(), Some(PhiError::InvalidConfig(_))));
+    }
+
+    #[tokio::test]
+    async fn test_drive_from_stream() {
+        let detector = Detector::new(10);
+        let t = Local::now();
+        let heartbeats = [0, 100, 220, 300, 410, 500].map(|ms| t + Duration::milliseconds(ms));
+        let inserted = detector.drive_from_stream(futures::stream::iter(heartbeats)).await.unwrap();
+
+        assert_eq!(6, inserted);
+        assert_eq!(heartbeats[5], detector.last_arrived_at().await.unwrap());
+        assert_eq!(vec![100, 120, 80, 110, 90], detector.with_intervals(|intervals| intervals.to_vec()).await);
+        assert_eq!(100., detector.stats_snapshot().await.mean);
+    }
+
+    #[tokio::test]
+    async fn test_median_spread_resists_outliers() {
+        let t = Local::now();
+        let plain = Detector::new(20);
+        let median = Detector::builder(20).with_median_spread().build();
+        let mut arrived_at = t;
+        for i in 0..21 {
+            for detector in [&plain, &median] {
+                detector.insert(arrived_at).await.unwrap();
+            }
+            // a bulk of 95, 100 and 105ms with two stalls
+            arrived_at += Duration::milliseconds(match i {
+                4 | 13 => 3000,
+                _ => 95 + i % 3 * 5,
+            });
+        }
+
+        let snapshot = median.stats_snapshot().await;
+        assert_eq!(100., snapshot.mean);
+        // MAD of 5ms
+        assert!((snapshot.variance.sqrt() - 7.413).abs() < 1e-9, "{}", snapshot.variance);
+        // the stalls put the plain mean past 380ms, so a heartbeat 200ms late barely registers
+        let query_at = arrived_at + Duration::milliseconds(300);
+        let plain_phi = plain.phi(query_at).await.unwrap();
+        assert!(plain_phi < 0.5, "{}", plain_phi);
+        assert!(median.phi(query_at).await.unwrap() > 8.);
+    }
+
+    #[tokio::test]
+    async fn test_has_heartbeat() {
+        let detector = Detector::new(10);
+        assert!(!detector.has_heartbeat());
+        detector.insert(Local::now()).await.unwrap();
+        assert!(detector.has_heartbeat());
+        assert!(detector.clone().has_heartbeat());
+    }
+
+    #[tokio::test]
+    async fn test_missed_heartbeats_raise_phi() {
+        let detector = Detector::new(10);
+        let t = Local::now();
+        let mut arrived_at = t;
+        for ms in [0, 100, 110, 90, 100] {
+            arrived_at += Duration::milliseconds(ms);
+            detector.insert(arrived_at).await.unwrap();
+        }
+        let on_time = detector.phi(arrived_at + Duration::milliseconds(50)).await.unwrap();
+
+        detector.report_missed_heartbeats(3);
+        let phi = detector.phi(arrived_at + Duration::milliseconds(50)).await.unwrap();
+        assert!((phi - on_time - 3. * MISSED_HEARTBEAT_PHI).abs() < 1e-9);
+
+        // 3 outstanding misses halve to 1, then to none
+        for expected in [1., 0.] {
+            arrived_at += Duration::milliseconds(100);
+            detector.insert(arrived_at).await.unwrap();
+            let phi = detector.phi(arrived_at + Duration::milliseconds(50)).await.unwrap();
+            let baseline = phi_from_params(50., 100., detector.stats_snapshot().await.variance, PhiBase::Log10);
+            assert!((phi - baseline - expected * MISSED_HEARTBEAT_PHI).abs() < 1e-9, "{} vs {}", phi, baseline);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_compact_intervals_saturate() {
+        let compact = Detector::builder(10).with_compact_intervals().build();
+        let t = Local::now();
+        for ms in [0, 100, 200] {
+            compact.insert(t + Duration::milliseconds(ms)).await.unwrap();
+        }
+        // 60 days is past the ~49.7 days a u32 holds in milliseconds
+        compact.insert(t + Duration::days(60)).await.unwrap();
+        let intervals = compact.with_intervals(|intervals| intervals.to_vec()).await;
+        assert_eq!(vec![100, 100, u32::MAX as u64], intervals);
+
+        let mut store = CompactIntervals::default();
+        store.push(u64::MAX);
+        store.push(7);
+        assert_eq!(Some(u32::MAX as u64), store.evict_oldest());
+        assert_eq!(Some(7), store.last());
+    }
+
+    #[test]
+    fn test_label_in_debug_output() {
+        let detector = Detector::builder(10).with_label("node-a").build();
+        assert_eq!(Some("node-a"), detector.label());
+        assert!(format!("{:?}", detector).starts_with("Detector { label: \"node-a\", window_length: 10"));
+        assert_eq!(None, Detector::new(10).label());
+    }
+
+    #[tokio::test]
+    async fn test_sla_ignores_interval_history() {
+        let steady = Detector::sla(1000., 100.);
+        let erratic = Detector::sla(1000., 100.);
+        let t = Local::now();
+        steady.insert(t).await.unwrap();
+        steady.insert(t + Duration::milliseconds(1000)).await.unwrap();
+        let mut arrived_at = t - Duration::seconds(60);
+        for ms in [20, 5000, 300, 12_000] {
+            arrived_at += Duration::milliseconds(ms);
+            assert_eq!(None, erratic.insert(arrived_at).await.unwrap());
+        }
+        erratic.insert(t + Duration::milliseconds(1000)).await.unwrap();
+
+        assert_eq!(0, erratic.statistics.read().await.len());
+        assert_eq!(StatsSnapshot { mean: 1000., variance: 10_000. }, erratic.stats_snapshot().await);
+        for elapsed in [500., 1000., 1200., 1500.] {
+            let query_at = t + Duration::milliseconds(1000 + elapsed as i64);
+            let expected = phi_from_params(elapsed, 1000., 10_000., PhiBase::Log10);
+            assert_eq!(expected, steady.phi(query_at).await.unwrap());
+            assert_eq!(expected, erratic.phi(query_at).await.unwrap());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_into_statistics() {
+        let detector = Detector::builder(4).with_insert_batching().build();
+        let t = Local::now();
+        for ms in [0, 100, 220, 300, 410] {
+            detector.insert(t + Duration::milliseconds(ms)).await.unwrap();
+        }
+        let history = detector.interval_history().await;
+        // queued but not applied until into_statistics
+        let guard = detector.statistics.write().await;
+        assert!(detector.insert(t + Duration::milliseconds(500)).await.unwrap().is_none());
+        drop(guard);
+
+        let stats = detector.into_statistics();
+        assert_eq!(4, stats.window_length());
+        assert_eq!(vec![120, 80, 110, 90], stats.intervals().collect::<Vec<_>>());
+        assert_eq!(history[1..], stats.arrival_times.iter().copied().zip(stats.intervals()).collect::<Vec<_>>()[..3]);
+        assert_eq!(Some(t + Duration::milliseconds(500)), stats.last_arrived_at());
+    }
+
+    #[tokio::test]
+    async fn test_pause_model_adds_base_and_scaled_std() {
+        let base = TimeDelta::milliseconds(200);
+        let model = Detector::builder(10).with_pause_model(base, 2.).build();
+        let strict = Detector::new(10);
+        assert_eq!(base, model.effective_pause());
+        let t = Local::now();
+        for ms in [0, 100, 220, 300, 410, 500] {
+            model.insert(t + Duration::milliseconds(ms)).await.unwrap();
+            strict.insert(t + Duration::milliseconds(ms)).await.unwrap();
+        }
+
+        // std of 100, 120, 80, 110 and 90 is ~14.14ms
+        let std = model.stats_snapshot().await.variance.sqrt();
+        let pause = base + TimeDelta::microseconds((2. * std * 1000.).round() as i64);
+        assert_eq!(pause, model.effective_pause());
+        assert_eq!(TimeDelta::microseconds(228_284), pause);
+        let query_at = t + Duration::milliseconds(800);
+        let shifted = strict.phi_with_snapshot(&strict.stats_snapshot().await, 300. - 200. - 2. * std);
+        assert!((model.phi(query_at).await.unwrap() - shifted).abs() < 1e-9);
+
+        model.set_acceptable_pause(TimeDelta::zero());
+        assert_eq!(TimeDelta::microseconds(28_284), model.effective_pause());
+    }
+
+    #[tokio::test]
+    async fn test_state_events_reach_every_subscriber() {
+        let detector = Detector::new(10);
+        let mut logging = detector.state_events();
+        let mut alerting = detector.state_events();
+        let t = Local::now();
+        for ms in [0, 100, 220, 300, 410, 500] {
+            detector.insert(t + Duration::milliseconds(ms)).await.unwrap();
+        }
+        assert!(!detector.is_suspected(t + Duration::milliseconds(600)).await.unwrap());
+        assert!(logging.try_recv().is_err());
+
+        let late = t + Duration::milliseconds(2000);
+        assert!(detector.is_suspected(late).await.unwrap());
+        assert!(detector.is_suspected(late).await.unwrap());
+        for receiver in [&mut logging, &mut alerting] {
+            let transition = receiver.try_recv().unwrap();
+            assert_eq!((NodeState::Up, NodeState::Suspected), (transition.from, transition.to));
+            assert_eq!(late, transition.timestamp);
+            assert!(transition.phi > 8.);
+            // a verdict that holds isn't a transition
+            assert!(receiver.try_recv().is_err());
+        }
+
+        detector.insert(late).await.unwrap();
+        assert!(!detector.is_suspected(late + Duration::milliseconds(50)).await.unwrap());
+        let StateTransition { from, to, .. } = alerting.try_recv().unwrap();
+        assert_eq!((NodeState::Suspected, NodeState::Up), (from, to));
+    }
+
+    #[tokio::test]
+    async fn test_health_score_is_logistic_around_threshold() {
+        let t = Local::now();
+        let at_threshold = t + Duration::milliseconds(645);
+        let probe = Detector::new(10);
+        for ms in [0, 100, 220, 300, 410, 500] {
+            probe.insert(t + Duration::milliseconds(ms)).await.unwrap();
+        }
+        // phi only moves with whole milliseconds, so center on a phi it actually takes
+        let threshold = probe.phi(at_threshold).await.unwrap();
+        assert!(threshold > 2.);
+        let detector = Detector::builder(10).with_threshold(threshold).with_health_steepness(2.).build();
+        for ms in [0, 100, 220, 300, 410, 500] {
+            detector.insert(t + Duration::milliseconds(ms)).await.unwrap();
+        }
+        let early = t + Duration::milliseconds(520);
+        assert!(detector.phi(early).await.unwrap() < 0.5);
+        assert!(detector.health_score(early).await.unwrap() > 0.99);
+        assert!((detector.health_score(at_threshold).await.unwrap() - 0.5).abs() < 1e-9);
+
+        let late = t + Duration::milliseconds(2000);
+        assert!(detector.health_score(late).await.unwrap() < 1e-6);
+        assert_eq!(0., detector.health_score(t + Duration::days(1)).await.unwrap());
+
+        assert!(Detector::builder(10).with_health_steepness(0.).build_checked().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_min_samples_withholds_every_phi() {
+        let builder = Detector::builder(10).with_min_samples(5);
+        let detector = builder.clone().build();
+        let sync = builder.build_sync();
+        let t = Local::now();
+        for ms in [0, 100, 220, 300] {
+            detector.insert(t + Duration::milliseconds(ms)).await.unwrap();
+            sync.insert(t + Duration::milliseconds(ms)).unwrap();
+        }
+        let q = t + Duration::milliseconds(400);
+        let not_enough = |err: Box<dyn std::error::Error>| err.downcast_ref() == Some(&PhiError::NotEnoughSamples);
+        assert!(not_enough(detector.phi(q).await.unwrap_err()));
+        assert!(not_enough(detector.status(q).await.unwrap_err()));
+        assert!(not_enough(detector.describe(q).await.unwrap_err()));
+        assert!(not_enough(detector.failure_probability(q).await.unwrap_err()));
+        assert!(not_enough(detector.cdf_value(q).await.unwrap_err()));
+        assert!(not_enough(detector.is_suspected(q).await.unwrap_err()));
+        assert!(not_enough(detector.is_suspected_prob(q).await.unwrap_err()));
+        assert!(not_enough(detector.severity(q).await.unwrap_err()));
+        assert!(not_enough(detector.health_score(q).await.unwrap_err()));
+        assert!(not_enough(detector.phi_detailed(q).await.unwrap_err()));
+        assert!(not_enough(detector.phi_after(TimeDelta::milliseconds(100)).await.unwrap_err()));
+        assert!(not_enough(detector.phi_trend(3, TimeDelta::milliseconds(10), q).await.unwrap_err()));
+        assert!(not_enough(detector.time_to_phi(8.).await.unwrap_err()));
+        assert!(not_enough(detector.effective_deadline(8.).await.unwrap_err()));
+        assert!(not_enough(detector.phi_at_percentile(50.).await.unwrap_err()));
+        assert!(not_enough(detector.view().await.phi(q).unwrap_err()));
+        assert!(not_enough(sync.phi(q).unwrap_err()));
+        assert_eq!(0., detector.phi_safe(q));
+
+        // the fifth interval warms it up
+        for ms in [410, 500] {
+            detector.insert(t + Duration::milliseconds(ms)).await.unwrap();
+            sync.insert(t + Duration::milliseconds(ms)).unwrap();
+        }
+        let q = t + Duration::milliseconds(600);
+        assert_eq!(detector.phi(q).await.unwrap(), detector.status(q).await.unwrap().phi);
+        assert_eq!(detector.phi(q).await.unwrap(), sync.phi(q).unwrap());
     }
 }
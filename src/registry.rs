@@ -0,0 +1,206 @@
+//! Detectors for a set of monitored nodes, keyed by node.
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::hash::Hash;
+use std::sync::Arc;
+use chrono::{DateTime, Local, TimeDelta};
+use futures::future::join_all;
+use crate::runtime::RwLock;
+use crate::{Detector, DetectorBuilder, PhiInteraction};
+
+/// One Detector per node, created with the registry's window length and acceptable pause on the
+/// node's first heartbeat or on register, or configured per node with register_with.
+#[derive(Debug)]
+pub struct DetectorRegistry<K> {
+    detectors: RwLock<HashMap<K, Arc<Detector>>>,
+    window_length: u32,
+    acceptable_pause: TimeDelta,
+}
+
+/// Cluster-level summary of the registry at a point in time, see DetectorRegistry::cluster_health.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ClusterHealth {
+    /// Nodes whose phi is at most the threshold.
+    pub up: usize,
+    /// Nodes whose phi exceeds the threshold.
+    pub suspected: usize,
+    /// Registered nodes without a heartbeat yet, counted as neither up nor suspected.
+    pub pending: usize,
+    /// suspected / (up + suspected), 0 if no node has heartbeated.
+    pub suspected_ratio: f64,
+}
+
+impl<K: Eq + Hash + Clone + Send + Sync> DetectorRegistry<K> {
+    /// New empty DetectorRegistry whose detectors have window_length and acceptable_pause.
+    pub fn new(window_length: u32, acceptable_pause: TimeDelta) -> Self {
+        DetectorRegistry {
+            detectors: RwLock::new(HashMap::new()),
+            window_length,
+            acceptable_pause,
+        }
+    }
+
+    /// Detector for node, created if it isn't registered yet.
+    pub async fn register(&self, node: K) -> Arc<Detector> {
+        if let Some(detector) = self.get(&node).await {
+            return detector;
+        }
+        let mut detectors = self.detectors.write().await;
+        let detector = detectors.entry(node).or_insert_with(|| {
+            Arc::new(Detector::with_acceptable_pause(self.window_length, self.acceptable_pause))
+        });
+        Arc::clone(detector)
+    }
+
+    /// Detector for node built by builder, e.g. with its own window length and acceptable pause
+    /// for a peer with a different latency profile. If node is already registered its detector
+    /// is kept, history included, and returned instead; register nodes before their first
+    /// heartbeat for the builder to take effect.
+    pub async fn register_with(&self, node: K, builder: DetectorBuilder) -> Arc<Detector> {
+        let mut detectors = self.detectors.write().await;
+        Arc::clone(detectors.entry(node).or_insert_with(|| Arc::new(builder.build())))
+    }
+
+    /// Detector for node, None if it isn't registered.
+    pub async fn get(&self, node: &K) -> Option<Arc<Detector>> {
+        self.detectors.read().await.get(node).cloned()
+    }
+
+    /// Stop monitoring node, returning its detector.
+    pub async fn remove(&self, node: &K) -> Option<Arc<Detector>> {
+        self.detectors.write().await.remove(node)
+    }
+
+    /// Number of registered nodes.
+    pub async fn len(&self) -> usize {
+        self.detectors.read().await.len()
+    }
+
+    /// Whether no node is registered.
+    pub async fn is_empty(&self) -> bool {
+        self.detectors.read().await.is_empty()
+    }
+
+    /// Insert a heartbeat from node, registering it on its first heartbeat. Returns the recorded
+    /// interval, see PhiInteraction::insert.
+    pub async fn insert(&self, node: K, arrived_at: DateTime<Local>) -> Result<Option<u64>, Box<dyn Error>> {
+        self.register(node).await.insert(arrived_at).await
+    }
+
+    /// Phi at t for every node, evaluated concurrently. The registry's lock is only held to
+    /// snapshot the detectors. Nodes without a heartbeat yet have no phi and are left out.
+    pub async fn phi_all(&self, t: DateTime<Local>) -> HashMap<K, f64> {
+        let detectors = self.snapshot().await;
+        let phis = join_all(detectors.iter().map(|(_, detector)| detector.phi(t))).await;
+        detectors.into_iter()
+            .zip(phis)
+            .filter_map(|((node, _), phi)| Some((node, phi.ok()?)))
+            .collect()
+    }
+
+    /// Nodes suspected at t by their detector's threshold, see Detector::is_suspected.
+    pub async fn suspected_nodes(&self, t: DateTime<Local>) -> Vec<K> {
+        let detectors = self.snapshot().await;
+        let suspected = join_all(detectors.iter().map(|(_, detector)| detector.is_suspected(t))).await;
+        detectors.into_iter()
+            .zip(suspected)
+            .filter_map(|((node, _), suspected)| suspected.unwrap_or(false).then_some(node))
+            .collect()
+    }
+
+    /// Up and suspected node counts at t for a single phi threshold across the cluster, e.g. to
+    /// alert once over half the nodes are suspected.
+    pub async fn cluster_health(&self, t: DateTime<Local>, threshold: f64) -> ClusterHealth {
+        let detectors = self.snapshot().await;
+        let phis = join_all(detectors.iter().map(|(_, detector)| detector.phi(t))).await;
+        let mut health = ClusterHealth { up: 0, suspected: 0, pending: 0, suspected_ratio: 0. };
+        for phi in phis {
+            match phi {
+                Ok(phi) if phi > threshold => health.suspected += 1,
+                Ok(_) => health.up += 1,
+                Err(_) => health.pending += 1,
+            }
+        }
+        let heartbeated = health.up + health.suspected;
+        if heartbeated > 0 {
+            health.suspected_ratio = health.suspected as f64 / heartbeated as f64;
+        }
+        health
+    }
+
+    async fn snapshot(&self) -> Vec<(K, Arc<Detector>)> {
+        let detectors = self.detectors.read().await;
+        detectors.iter().map(|(node, detector)| (node.clone(), Arc::clone(detector))).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Local, TimeDelta};
+    use crate::{Detector, PhiInteraction};
+    use super::{ClusterHealth, DetectorRegistry};
+
+    #[tokio::test]
+    async fn test_phi_all() {
+        let registry = DetectorRegistry::new(10, TimeDelta::zero());
+        let t = Local::now();
+        for (node, interval) in [("a", 100), ("b", 200), ("c", 300)] {
+            for i in 0..5 {
+                registry.insert(node, t + TimeDelta::milliseconds(i * interval)).await.unwrap();
+            }
+        }
+        registry.register("silent").await;
+
+        let query_at = t + TimeDelta::milliseconds(1500);
+        let phis = registry.phi_all(query_at).await;
+        assert_eq!(3, phis.len());
+        for node in ["a", "b", "c"] {
+            let expected = registry.get(&node).await.unwrap().phi(query_at).await.unwrap();
+            assert_eq!(expected, phis[node]);
+        }
+        assert!(!phis.contains_key("silent"));
+        assert_eq!(vec!["a", "b"], {
+            let mut suspected = registry.suspected_nodes(query_at).await;
+            suspected.sort();
+            suspected
+        });
+    }
+
+    #[tokio::test]
+    async fn test_register_with_per_node_pause() {
+        let registry = DetectorRegistry::new(10, TimeDelta::zero());
+        let lenient = registry.register_with("lenient", Detector::builder(10)
+            .with_acceptable_pause(TimeDelta::milliseconds(1000))).await;
+        assert!(std::sync::Arc::ptr_eq(&lenient, &registry.register_with("lenient", Detector::builder(5)).await));
+        let t = Local::now();
+        for node in ["lenient", "strict"] {
+            for i in 0..5 {
+                registry.insert(node, t + TimeDelta::milliseconds(i * 100 + i % 2 * 10)).await.unwrap();
+            }
+        }
+
+        assert_eq!(TimeDelta::milliseconds(1000), registry.get(&"lenient").await.unwrap().acceptable_pause());
+        assert_eq!(vec!["strict"], registry.suspected_nodes(t + TimeDelta::milliseconds(900)).await);
+    }
+
+    #[tokio::test]
+    async fn test_cluster_health() {
+        let registry = DetectorRegistry::new(10, TimeDelta::zero());
+        let t = Local::now();
+        for node in ["a", "b", "c", "silent-1", "silent-2"] {
+            for i in 0..5 {
+                registry.insert(node, t + TimeDelta::milliseconds(i * 100 + i % 2 * 10)).await.unwrap();
+            }
+        }
+        for node in ["a", "b", "c"] {
+            registry.insert(node, t + TimeDelta::milliseconds(1000)).await.unwrap();
+        }
+        registry.register("pending").await;
+
+        let health = registry.cluster_health(t + TimeDelta::milliseconds(1050), 8.).await;
+        assert_eq!(ClusterHealth { up: 3, suspected: 2, pending: 1, suspected_ratio: 0.4 }, health);
+        let empty = DetectorRegistry::<&str>::new(10, TimeDelta::zero()).cluster_health(t, 8.).await;
+        assert_eq!(0., empty.suspected_ratio);
+    }
+}
@@ -0,0 +1,93 @@
+//! Async runtime the detector is built against, picked with the `runtime-tokio` (default) or
+//! `runtime-async-std` feature. At least one of the two must be enabled; should both be, e.g.
+//! when another dependency turns on the default one, tokio takes precedence and async-std goes
+//! unused. Depend on the crate with `default-features = false` to switch to async-std.
+//!
+//! tokio itself is a dependency either way: its runtime-independent `sync` primitives, the
+//! channels, Notify for Detector::watch and the state events' broadcast, along with `select!`
+//! and `pin!`, are used whatever the runtime. They don't need a tokio runtime to run, so under
+//! async-std only that small part of tokio is compiled, without its scheduler, timer or I/O.
+
+use std::future::Future;
+use std::time::Duration;
+
+#[cfg(not(any(feature = "runtime-tokio", feature = "runtime-async-std")))]
+compile_error!("one of the features `runtime-tokio` or `runtime-async-std` must be enabled");
+
+#[cfg(feature = "runtime-tokio")]
+pub(crate) use tokio::sync::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+#[cfg(all(feature = "runtime-async-std", not(feature = "runtime-tokio")))]
+pub(crate) use async_std::sync::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+/// Read guard if the lock is free right now.
+#[cfg(feature = "runtime-tokio")]
+pub(crate) fn try_read<T>(lock: &RwLock<T>) -> Option<RwLockReadGuard<'_, T>> {
+    lock.try_read().ok()
+}
+
+/// Read guard if the lock is free right now.
+#[cfg(all(feature = "runtime-async-std", not(feature = "runtime-tokio")))]
+pub(crate) fn try_read<T>(lock: &RwLock<T>) -> Option<RwLockReadGuard<'_, T>> {
+    lock.try_read()
+}
+
+/// Write guard if the lock is free right now.
+#[cfg(feature = "runtime-tokio")]
+pub(crate) fn try_write<T>(lock: &RwLock<T>) -> Option<RwLockWriteGuard<'_, T>> {
+    lock.try_write().ok()
+}
+
+/// Write guard if the lock is free right now.
+#[cfg(all(feature = "runtime-async-std", not(feature = "runtime-tokio")))]
+pub(crate) fn try_write<T>(lock: &RwLock<T>) -> Option<RwLockWriteGuard<'_, T>> {
+    lock.try_write()
+}
+
+/// Run future in the background on the runtime.
+pub(crate) fn spawn<F>(future: F)
+where
+    F: Future<Output = ()> + Send + 'static,
+{
+    #[cfg(feature = "runtime-tokio")]
+    tokio::spawn(future);
+    #[cfg(all(feature = "runtime-async-std", not(feature = "runtime-tokio")))]
+    async_std::task::spawn(future);
+}
+
+/// Sleep on the runtime's timer.
+pub(crate) async fn sleep(duration: Duration) {
+    #[cfg(feature = "runtime-tokio")]
+    tokio::time::sleep(duration).await;
+    #[cfg(all(feature = "runtime-async-std", not(feature = "runtime-tokio")))]
+    async_std::task::sleep(duration).await;
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::time::Duration;
+    use chrono::Local;
+    use crate::{Detector, PhiInteraction};
+
+    async fn subscribe_and_clone() {
+        let detector = Arc::new(Detector::new(10));
+        detector.insert(Local::now()).await.unwrap();
+        let mut rx = detector.subscribe(Duration::from_millis(5));
+        assert!(rx.recv().await.is_some());
+        let fork = detector.clone();
+        assert_eq!(detector.last_arrived_at().await.unwrap(), fork.last_arrived_at().await.unwrap());
+    }
+
+    #[cfg(feature = "runtime-tokio")]
+    #[tokio::test]
+    async fn test_runtime_tokio() {
+        subscribe_and_clone().await;
+    }
+
+    #[cfg(all(feature = "runtime-async-std", not(feature = "runtime-tokio")))]
+    #[async_std::test]
+    async fn test_runtime_async_std() {
+        subscribe_and_clone().await;
+    }
+}
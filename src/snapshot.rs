@@ -0,0 +1,209 @@
+//! Compact binary encoding of a detector's statistics, see Detector::to_bytes.
+//!
+//! All integers are little-endian. Version 1 is laid out as
+//!
+//! | bytes | field                                                        |
+//! |-------|--------------------------------------------------------------|
+//! | 1     | format version, 1                                            |
+//! | 1     | interval unit, 0 for milliseconds and 1 for microseconds     |
+//! | 4     | window_length, u32                                           |
+//! | 4     | heartbeats in window n, u32, 0 before the first heartbeat    |
+//! | 8     | last arrival in nanoseconds since the Unix epoch, i64        |
+//! | 8     | clock regressions, u64                                       |
+//! | 1     | 1 if the window has an EWMA, then its alpha as an f64        |
+//! | 4     | interval count, u32, followed by the intervals as u64 each   |
+//!
+//! A reader rejects versions it doesn't know, so a later version can change the layout freely.
+//! Arrival times aren't stored but rebuilt from the intervals back from the last arrival.
+
+use std::error::Error;
+use chrono::{DateTime, Local, TimeDelta};
+use crate::{Ewma, IntervalUnit, PhiError, Statistics};
+
+const VERSION: u8 = 1;
+
+impl Statistics {
+    /// Encode the statistics in the current format version.
+    pub(crate) fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(39 + 8 * self.arrival_intervals.len());
+        bytes.push(VERSION);
+        bytes.push(match self.unit {
+            IntervalUnit::Milliseconds => 0,
+            IntervalUnit::Microseconds => 1,
+        });
+        bytes.extend_from_slice(&self.window_length.to_le_bytes());
+        bytes.extend_from_slice(&self.n.to_le_bytes());
+        let nanos = self.last_arrived_at.timestamp_nanos_opt().unwrap_or(i64::MAX);
+        bytes.extend_from_slice(&nanos.to_le_bytes());
+        bytes.extend_from_slice(&self.clock_regressions.to_le_bytes());
+        match &self.ewma {
+            Some(ewma) => {
+                bytes.push(1);
+                bytes.extend_from_slice(&ewma.alpha.to_le_bytes());
+            }
+            None => bytes.push(0),
+        }
+        bytes.extend_from_slice(&(self.arrival_intervals.len() as u32).to_le_bytes());
+        for interval in self.arrival_intervals.iter() {
+            bytes.extend_from_slice(&interval.to_le_bytes());
+        }
+        bytes
+    }
+
+    /// Decode statistics encoded by to_bytes, failing with PhiError::CorruptSnapshot on anything
+    /// but a well-formed snapshot of a known version.
+    pub(crate) fn from_bytes(bytes: &[u8]) -> Result<Self, Box<dyn Error>> {
+        let mut reader = Reader(bytes);
+        let version = reader.u8()?;
+        if version != VERSION {
+            return Err(corrupt(format!("unknown format version {}", version)));
+        }
+        let unit = match reader.u8()? {
+            0 => IntervalUnit::Milliseconds,
+            1 => IntervalUnit::Microseconds,
+            unit => return Err(corrupt(format!("unknown interval unit {}", unit))),
+        };
+        let window_length = u32::from_le_bytes(reader.take()?);
+        let n = u32::from_le_bytes(reader.take()?);
+        let last_arrived_at = DateTime::from_timestamp_nanos(i64::from_le_bytes(reader.take()?));
+        let clock_regressions = u64::from_le_bytes(reader.take()?);
+        let alpha = match reader.u8()? {
+            0 => None,
+            1 => Some(f64::from_le_bytes(reader.take()?)),
+            flag => return Err(corrupt(format!("invalid EWMA flag {}", flag))),
+        };
+        let count = u32::from_le_bytes(reader.take()?);
+        if window_length == 0 || count > window_length {
+            return Err(corrupt(format!("{} intervals don't fit a window of {}", count, window_length)));
+        }
+        if (n == 0 && count > 0) || (n > 0 && n != count + 1) {
+            return Err(corrupt(format!("{} heartbeats don't match {} intervals", n, count)));
+        }
+        // checked before allocating so a corrupt count can't request a huge buffer
+        if reader.0.len() / 8 < count as usize {
+            return Err(corrupt("truncated snapshot".into()));
+        }
+        let mut arrival_intervals = Vec::with_capacity(count as usize);
+        for _ in 0..count {
+            arrival_intervals.push(u64::from_le_bytes(reader.take()?));
+        }
+        if !reader.0.is_empty() {
+            return Err(corrupt(format!("{} trailing bytes", reader.0.len())));
+        }
+
+        let mut stats = Statistics::new(window_length);
+        if let Some(alpha) = alpha {
+            if !(alpha > 0. && alpha <= 1.) {
+                return Err(corrupt(format!("invalid EWMA alpha {}", alpha)));
+            }
+            let mut ewma = Ewma::new(alpha);
+            for interval in &arrival_intervals {
+                ewma.update(*interval);
+            }
+            stats.ewma = Some(ewma);
+        }
+        let last_arrived_at = last_arrived_at.with_timezone(&Local);
+        let mut arrived_at = last_arrived_at;
+        for interval in arrival_intervals.iter().rev() {
+            stats.arrival_times.push_front(arrived_at);
+            // every arrival must fit the format's nanosecond timestamps, as the last one does
+            arrived_at = i64::try_from(*interval)
+                .ok()
+                .and_then(|units| match unit {
+                    IntervalUnit::Milliseconds => TimeDelta::try_milliseconds(units),
+                    IntervalUnit::Microseconds => Some(TimeDelta::microseconds(units)),
+                })
+                .and_then(|interval| arrived_at.checked_sub_signed(interval))
+                .filter(|arrived_at| arrived_at.timestamp_nanos_opt().is_some())
+                .ok_or_else(|| corrupt(format!("intervals run past {} before the last arrival", arrived_at)))?;
+        }
+        stats.arrival_intervals = Box::new(arrival_intervals);
+        stats.last_arrived_at = last_arrived_at;
+        stats.n = n;
+        stats.clock_regressions = clock_regressions;
+        stats.unit = unit;
+        Ok(stats)
+    }
+}
+
+fn corrupt(reason: String) -> Box<dyn Error> {
+    PhiError::CorruptSnapshot(reason).into()
+}
+
+/// Cursor over the bytes left to decode.
+struct Reader<'a>(&'a [u8]);
+
+impl Reader<'_> {
+    fn take<const N: usize>(&mut self) -> Result<[u8; N], Box<dyn Error>> {
+        if self.0.len() < N {
+            return Err(corrupt("truncated snapshot".into()));
+        }
+        let (head, rest) = self.0.split_at(N);
+        self.0 = rest;
+        Ok(head.try_into().expect("split_at returns N bytes"))
+    }
+
+    fn u8(&mut self) -> Result<u8, Box<dyn Error>> {
+        Ok(self.take::<1>()?[0])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Local, TimeDelta};
+    use crate::{Detector, PhiError, PhiInteraction};
+
+    #[tokio::test]
+    async fn test_round_trip() {
+        let detector = Detector::with_ewma(10, 0.2);
+        let t = Local::now();
+        for ms in [0, 100, 220, 300, 410, 480] {
+            detector.insert(t + TimeDelta::milliseconds(ms)).await.unwrap();
+        }
+        let bytes = detector.to_bytes().await;
+        assert_eq!(39 + 8 * 5, bytes.len());
+
+        let restored = Detector::from_bytes(&bytes).unwrap();
+        assert_eq!(detector.interval_history().await, restored.interval_history().await);
+        assert_eq!(detector.last_arrived_at().await.unwrap(), restored.last_arrived_at().await.unwrap());
+        let query_at = t + TimeDelta::milliseconds(600);
+        assert_eq!(detector.phi(query_at).await.unwrap(), restored.phi(query_at).await.unwrap());
+        assert_eq!(bytes, restored.to_bytes().await);
+    }
+
+    #[tokio::test]
+    async fn test_corrupt_input_is_an_error() {
+        let detector = Detector::new(10);
+        let t = Local::now();
+        for ms in [0, 100, 220] {
+            detector.insert(t + TimeDelta::milliseconds(ms)).await.unwrap();
+        }
+        let bytes = detector.to_bytes().await;
+        let is_corrupt = |bytes: &[u8]| {
+            let err = Detector::from_bytes(bytes).unwrap_err();
+            matches!(err.downcast_ref::<PhiError>(), Some(PhiError::CorruptSnapshot(_)))
+        };
+        for len in 0..bytes.len() {
+            assert!(is_corrupt(&bytes[..len]), "truncated to {}", len);
+        }
+        let mut wrong_version = bytes.clone();
+        wrong_version[0] = 2;
+        assert!(is_corrupt(&wrong_version));
+        let mut trailing = bytes.clone();
+        trailing.push(0);
+        assert!(is_corrupt(&trailing));
+        let mut zero_window = bytes.clone();
+        zero_window[2..6].copy_from_slice(&0u32.to_le_bytes());
+        assert!(is_corrupt(&zero_window));
+        // the first interval follows the 31 byte header without an EWMA
+        let mut oversized = bytes.clone();
+        oversized[31..39].copy_from_slice(&u64::MAX.to_le_bytes());
+        assert!(is_corrupt(&oversized));
+        let mut past_range = bytes.clone();
+        past_range[31..39].copy_from_slice(&(i64::MAX as u64 / 1000).to_le_bytes());
+        assert!(is_corrupt(&past_range));
+        let mut earliest = bytes.clone();
+        earliest[10..18].copy_from_slice(&i64::MIN.to_le_bytes());
+        assert!(is_corrupt(&earliest));
+    }
+}
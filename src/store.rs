@@ -0,0 +1,145 @@
+//! Storage backing a window's intervals, see IntervalStore.
+
+use std::collections::VecDeque;
+use std::fmt;
+
+/// Storage for the intervals in a Statistics window, oldest first, so large fleets can keep
+/// windows outside the heap, e.g. in a memory-mapped file or a shared buffer. Statistics only
+/// pushes at the back and evicts from the front; the window length is enforced by Statistics, not
+/// the store. Vec<u64> is the default and VecDeque<u64> evicts in constant time.
+///
+/// Only the intervals go through the store: the arrival time of each stays in memory. For a
+/// smaller footprint in memory, see CompactIntervals.
+pub trait IntervalStore: fmt::Debug + Send + Sync {
+    /// Append interval as the newest.
+    fn push(&mut self, interval: u64);
+
+    /// Remove and return the oldest interval, None if empty.
+    fn evict_oldest(&mut self) -> Option<u64>;
+
+    /// Intervals oldest first.
+    fn iter(&self) -> Box<dyn Iterator<Item = u64> + '_>;
+
+    /// Number of intervals stored.
+    fn len(&self) -> usize;
+
+    /// Whether no interval is stored.
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Newest interval, None if empty. Override when the store can do better than a full scan.
+    fn last(&self) -> Option<u64> {
+        self.iter().last()
+    }
+
+    /// Independent copy of the store with the same intervals, for cloning Statistics.
+    fn clone_store(&self) -> Box<dyn IntervalStore>;
+
+    /// Intervals oldest first as a contiguous slice, if the store holds them as one, letting
+    /// Detector::with_intervals skip the copy. None by default.
+    fn as_slice(&self) -> Option<&[u64]> {
+        None
+    }
+}
+
+impl Clone for Box<dyn IntervalStore> {
+    fn clone(&self) -> Self {
+        self.clone_store()
+    }
+}
+
+impl IntervalStore for Vec<u64> {
+    fn push(&mut self, interval: u64) {
+        Vec::push(self, interval);
+    }
+
+    fn evict_oldest(&mut self) -> Option<u64> {
+        if self.is_empty() {
+            None
+        } else {
+            Some(self.remove(0))
+        }
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = u64> + '_> {
+        Box::new(self.as_slice().iter().copied())
+    }
+
+    fn len(&self) -> usize {
+        Vec::len(self)
+    }
+
+    fn last(&self) -> Option<u64> {
+        self.as_slice().last().copied()
+    }
+
+    fn clone_store(&self) -> Box<dyn IntervalStore> {
+        Box::new(self.clone())
+    }
+
+    fn as_slice(&self) -> Option<&[u64]> {
+        Some(Vec::as_slice(self))
+    }
+}
+
+impl IntervalStore for VecDeque<u64> {
+    fn push(&mut self, interval: u64) {
+        self.push_back(interval);
+    }
+
+    fn evict_oldest(&mut self) -> Option<u64> {
+        self.pop_front()
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = u64> + '_> {
+        Box::new(VecDeque::iter(self).copied())
+    }
+
+    fn len(&self) -> usize {
+        VecDeque::len(self)
+    }
+
+    fn last(&self) -> Option<u64> {
+        self.back().copied()
+    }
+
+    fn clone_store(&self) -> Box<dyn IntervalStore> {
+        Box::new(self.clone())
+    }
+}
+
+/// Intervals stored as u32, half the footprint of the default store. The window as a whole
+/// shrinks less: each entry also keeps its 16 byte arrival time outside the store, so it drops from
+/// about 24 to 20 bytes. An interval beyond u32::MAX saturates to it rather than wrapping: the
+/// longest representable interval is ~49.7 days in IntervalUnit::Milliseconds, plenty for
+/// heartbeats, but only ~71.6 minutes in IntervalUnit::Microseconds. The math is done in f64
+/// either way.
+#[derive(Clone, Debug, Default)]
+pub struct CompactIntervals(VecDeque<u32>);
+
+impl IntervalStore for CompactIntervals {
+    fn push(&mut self, interval: u64) {
+        self.0.push_back(u32::try_from(interval).unwrap_or(u32::MAX));
+    }
+
+    fn evict_oldest(&mut self) -> Option<u64> {
+        self.0.pop_front().map(u64::from)
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = u64> + '_> {
+        Box::new(self.0.iter().map(|interval| u64::from(*interval)))
+    }
+
+    fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    fn last(&self) -> Option<u64> {
+        self.0.back().map(|interval| u64::from(*interval))
+    }
+
+    fn clone_store(&self) -> Box<dyn IntervalStore> {
+        Box::new(self.clone())
+    }
+}
@@ -0,0 +1,223 @@
+//! Detector behind a std lock for callers without an async runtime, see SyncDetector.
+
+use std::error::Error;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, PoisonError, RwLock};
+use chrono::{DateTime, Local};
+use crate::{Detector, DetectorBuilder, PhiCore, PhiError, Statistics};
+
+/// Blocking counterpart of Detector, built with DetectorBuilder::build_sync, whose statistics sit
+/// behind a std RwLock instead of the runtime's. Unlike the runtime's locks a std lock is
+/// poisoned by a panic while it is held, e.g. in the insert callback: every call then fails with
+/// PhiError::LockPoisoned rather than panicking or reading statistics a write left half done,
+/// until clear_poison restores the statistics as of the last completed write.
+///
+/// To have those at hand a write that runs caller code, an insert with an insert callback
+/// configured or with_statistics_mut, first copies the statistics, so it costs a clone of the
+/// window on top of the insert. Other inserts don't. Insert batching doesn't apply.
+pub struct SyncDetector {
+    // config and lock-free state, its own statistics stay empty
+    detector: Detector,
+    statistics: RwLock<Statistics>,
+    // statistics as of before the write that panicked, restored by clear_poison; None if it
+    // ran no caller code and so kept no copy
+    last_good: Mutex<Option<Statistics>>,
+    // tracked next to the lock rather than with std's poison flag: RwLock::clear_poison needs
+    // Rust 1.77, past the MSRV
+    poisoned: AtomicBool,
+}
+
+/// Marks the lock poisoned if dropped while panicking, like std's own guards, handing the copy
+/// of the statistics taken before the write to clear_poison.
+struct PoisonOnPanic<'a> {
+    detector: &'a SyncDetector,
+    before: Option<Statistics>,
+}
+
+impl Drop for PoisonOnPanic<'_> {
+    fn drop(&mut self) {
+        if std::thread::panicking() {
+            *self.detector.last_good.lock().unwrap_or_else(PoisonError::into_inner) = self.before.take();
+            self.detector.poisoned.store(true, Ordering::Relaxed);
+        }
+    }
+}
+
+impl DetectorBuilder {
+    /// Build the configured detector as a SyncDetector.
+    pub fn build_sync(self) -> SyncDetector {
+        let mut detector = self.build();
+        let statistics = std::mem::replace(detector.statistics.get_mut(), Statistics::new(1));
+        SyncDetector {
+            detector,
+            last_good: Mutex::new(None),
+            statistics: RwLock::new(statistics),
+            poisoned: AtomicBool::new(false),
+        }
+    }
+}
+
+impl SyncDetector {
+    /// New SyncDetector with window_length and no acceptable pause, see Detector::new.
+    pub fn new(window_length: u32) -> Self {
+        Detector::builder(window_length).build_sync()
+    }
+
+    /// Insert a heartbeat, returning the interval it closed as Detector's insert does.
+    pub fn insert(&self, arrived_at: DateTime<Local>) -> Result<Option<u64>, Box<dyn Error>> {
+        let run_callback = self.detector.config.on_insert.is_some();
+        Ok(self.write(run_callback, |detector, stats| {
+            let recorded = detector.record_heartbeat(stats, arrived_at);
+            detector.heartbeat_inserted(stats, recorded);
+            recorded
+        })?)
+    }
+
+    /// Phi at t, see Detector::phi.
+    pub fn phi(&self, t: DateTime<Local>) -> Result<f64, Box<dyn Error>> {
+        if let Some(phi) = self.detector.overridden_phi(t) {
+            self.check_poison()?;
+            return Ok(phi);
+        }
+        let stats = self.read()?;
+        Ok(self.detector.phi_with_stats(&stats, t)?)
+    }
+
+    /// Last arrival time of heartbeat, see Detector::last_arrived_at.
+    pub fn last_arrived_at(&self) -> Result<DateTime<Local>, Box<dyn Error>> {
+        Ok(self.read()?.last_heartbeat().ok_or(PhiError::NotEnoughSamples)?)
+    }
+
+    /// Run f on the statistics under the write lock, e.g. to insert intervals measured elsewhere.
+    /// A panic in f poisons the lock.
+    pub fn with_statistics_mut<R>(&self, f: impl FnOnce(&mut Statistics) -> R) -> Result<R, Box<dyn Error>> {
+        Ok(self.write(true, |detector, stats| {
+            let result = f(stats);
+            detector.refresh(stats);
+            result
+        })?)
+    }
+
+    /// Whether a panic under the lock left it poisoned, see clear_poison.
+    pub fn is_poisoned(&self) -> bool {
+        self.poisoned.load(Ordering::Relaxed)
+    }
+
+    /// Recover from a poisoned lock: the statistics roll back to the last completed write, losing
+    /// whatever the panicking one did, and calls succeed again. Returns whether the lock was
+    /// poisoned. Only a panic in the crate itself, outside caller code, leaves nothing to roll
+    /// back to: the statistics are then kept as the panic left them.
+    pub fn clear_poison(&self) -> bool {
+        let mut stats = self.statistics.write().unwrap_or_else(PoisonError::into_inner);
+        if !self.poisoned.swap(false, Ordering::Relaxed) {
+            return false;
+        }
+        if let Some(last_good) = self.last_good.lock().unwrap_or_else(PoisonError::into_inner).take() {
+            *stats = last_good;
+        }
+        self.detector.refresh(&stats);
+        true
+    }
+
+    fn check_poison(&self) -> Result<(), PhiError> {
+        if self.is_poisoned() {
+            return Err(PhiError::LockPoisoned);
+        }
+        Ok(())
+    }
+
+    fn read(&self) -> Result<std::sync::RwLockReadGuard<'_, Statistics>, PhiError> {
+        let stats = self.statistics.read().unwrap_or_else(PoisonError::into_inner);
+        self.check_poison()?;
+        Ok(stats)
+    }
+
+    /// Run f under the write lock, copying the statistics first if runs_caller_code, as only
+    /// caller code is expected to panic.
+    fn write<R>(&self, runs_caller_code: bool, f: impl FnOnce(&Detector, &mut Statistics) -> R) -> Result<R, PhiError> {
+        let mut stats = self.statistics.write().unwrap_or_else(PoisonError::into_inner);
+        self.check_poison()?;
+        let poison = PoisonOnPanic { detector: self, before: runs_caller_code.then(|| stats.clone()) };
+        let result = f(&self.detector, &mut stats);
+        drop(poison);
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::thread;
+    use chrono::{Local, TimeDelta};
+    use crate::{Detector, PhiError, PhiInteraction, SyncDetector};
+
+    #[test]
+    fn test_poisoned_lock_is_an_error_until_cleared() {
+        let detector = Arc::new(Detector::builder(10)
+            .with_on_insert(|interval, _| assert!(interval < 500, "slow heartbeat"))
+            .build_sync());
+        let t = Local::now();
+        for ms in [0, 100, 220, 300] {
+            detector.insert(t + TimeDelta::milliseconds(ms)).unwrap();
+        }
+        let query_at = t + TimeDelta::milliseconds(400);
+        let phi = detector.phi(query_at).unwrap();
+
+        let panicking = Arc::clone(&detector);
+        let joined = thread::spawn(move || {
+            panicking.insert(t + TimeDelta::milliseconds(900)).unwrap();
+        }).join();
+        assert!(joined.is_err());
+        assert!(detector.is_poisoned());
+        let is_poisoned = |err: Box<dyn std::error::Error>| err.downcast_ref() == Some(&PhiError::LockPoisoned);
+        assert!(is_poisoned(detector.phi(query_at).unwrap_err()));
+        assert!(is_poisoned(detector.insert(t + TimeDelta::milliseconds(1000)).unwrap_err()));
+        assert!(is_poisoned(detector.last_arrived_at().unwrap_err()));
+
+        // the interval of the panicking insert was recorded before the callback, and is rolled back
+        assert!(detector.clear_poison());
+        assert!(!detector.clear_poison());
+        assert_eq!(t + TimeDelta::milliseconds(300), detector.last_arrived_at().unwrap());
+        assert_eq!(phi, detector.phi(query_at).unwrap());
+        assert_eq!(Some(100), detector.insert(query_at).unwrap());
+    }
+
+    #[test]
+    fn test_panic_in_with_statistics_mut_is_rolled_back() {
+        let detector = Arc::new(SyncDetector::new(10));
+        let t = Local::now();
+        for ms in [0, 100, 220, 300] {
+            detector.insert(t + TimeDelta::milliseconds(ms)).unwrap();
+        }
+        let panicking = Arc::clone(&detector);
+        let joined = thread::spawn(move || {
+            panicking.with_statistics_mut(|stats| {
+                stats.insert_interval(5000);
+                panic!("half done");
+            }).unwrap();
+        }).join();
+        assert!(joined.is_err());
+        assert!(detector.clear_poison());
+        let intervals = detector.with_statistics_mut(|stats| stats.intervals().collect::<Vec<_>>()).unwrap();
+        assert_eq!(vec![100, 120, 80], intervals);
+    }
+
+    #[test]
+    fn test_sync_detector_matches_detector() {
+        let sync = SyncDetector::new(10);
+        let detector = Detector::new(10);
+        let t = Local::now();
+        let runtime = tokio::runtime::Builder::new_current_thread().build().unwrap();
+        for ms in [0, 100, 220, 300, 410] {
+            sync.insert(t + TimeDelta::milliseconds(ms)).unwrap();
+            runtime.block_on(detector.insert(t + TimeDelta::milliseconds(ms))).unwrap();
+        }
+        sync.with_statistics_mut(|stats| stats.insert_interval(90)).unwrap();
+        runtime.block_on(detector.insert_interval(90)).unwrap();
+        for ms in [520, 600, 800] {
+            let query_at = t + TimeDelta::milliseconds(ms);
+            let expected = runtime.block_on(detector.phi(query_at)).unwrap();
+            assert_eq!(expected, sync.phi(query_at).unwrap());
+        }
+    }
+}
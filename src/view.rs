@@ -0,0 +1,140 @@
+//! Consistent read-only view of a detector's statistics, see Detector::view.
+
+use std::error::Error;
+use chrono::{DateTime, Local};
+use crate::runtime::RwLockReadGuard;
+use crate::{Detector, PhiCore, PhiError, Statistics};
+
+/// Statistics of a Detector as of Detector::view, for deriving several metrics from one
+/// consistent window without going through the lock for each. The view holds the read lock:
+/// inserts wait until it is dropped, so keep it short-lived and don't hold it across long
+/// awaits.
+#[derive(Debug)]
+pub struct DetectorView<'a> {
+    detector: &'a Detector,
+    stats: RwLockReadGuard<'a, Statistics>,
+    variance: f64,
+    mean: f64,
+}
+
+impl Detector {
+    /// Read-only view of the statistics as they are now, see DetectorView.
+    pub async fn view(&self) -> DetectorView<'_> {
+        let stats = self.statistics.read().await;
+        let (variance, mean) = self.distribution(&stats);
+        DetectorView { detector: self, stats, variance, mean }
+    }
+
+    /// Phi of a heartbeat arriving exactly at the p-th percentile of the intervals in window,
+    /// with the acceptable pause and max_phi applied as in phi, to check what the threshold
+    /// means against the intervals actually seen: a threshold below the phi at the 99th
+    /// percentile flags one in a hundred healthy intervals. Overrides and the startup grace
+    /// don't apply. Fails with PhiError::NotEnoughSamples for an empty window or one short of
+    /// the min samples; panics if p is
+    /// outside 0 to 100.
+    pub async fn phi_at_percentile(&self, p: f64) -> Result<f64, Box<dyn Error>> {
+        assert!((0. ..=100.).contains(&p), "percentile {} is outside 0 to 100", p);
+        let view = self.view().await;
+        self.warm_heartbeat(&view.stats)?;
+        let elapsed_ms = view.percentile(p).ok_or(PhiError::NotEnoughSamples)?;
+        Ok(self.phi_at(elapsed_ms, view.variance, view.mean))
+    }
+}
+
+impl DetectorView<'_> {
+    /// Mean interval in milliseconds phi is computed from, see Detector::stats_snapshot.
+    pub fn mean(&self) -> f64 {
+        self.mean
+    }
+
+    /// Variance in milliseconds squared phi is computed from, see Detector::stats_snapshot.
+    pub fn variance(&self) -> f64 {
+        self.variance
+    }
+
+    /// Phi at t as Detector::phi would compute it from the viewed statistics.
+    pub fn phi(&self, t: DateTime<Local>) -> Result<f64, Box<dyn Error>> {
+        if let Some(phi) = self.detector.overridden_phi(t) {
+            return Ok(phi);
+        }
+        let last_arrived_at = self.detector.warm_heartbeat(&self.stats)?;
+        let elapsed_ms = self.detector.elapsed_ms_between(last_arrived_at, t);
+        Ok(self.detector.phi_at(elapsed_ms, self.variance, self.mean))
+    }
+
+    /// Interval in milliseconds below which p percent of the intervals in window fall,
+    /// interpolating linearly between the closest ranks. None for an empty window or a p
+    /// outside 0 to 100.
+    pub fn percentile(&self, p: f64) -> Option<f64> {
+        if self.stats.is_empty() || !(0. ..=100.).contains(&p) {
+            return None;
+        }
+        let mut sorted: Vec<u64> = self.stats.intervals().collect();
+        sorted.sort_unstable();
+        let rank = p / 100. * (sorted.len() - 1) as f64;
+        let (below, above) = (sorted[rank.floor() as usize] as f64, sorted[rank.ceil() as usize] as f64);
+        let interval = below + (above - below) * rank.fract();
+        Some(interval / self.stats.unit.per_ms() as f64)
+    }
+
+    /// Number of intervals in window.
+    pub fn sample_count(&self) -> usize {
+        self.stats.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Local, TimeDelta};
+    use crate::{Detector, PhiInteraction};
+
+    #[tokio::test]
+    async fn test_view_matches_locked_reads() {
+        let detector = Detector::with_acceptable_pause(10, TimeDelta::milliseconds(20));
+        let t = Local::now();
+        for ms in [0, 100, 220, 300, 410, 500] {
+            detector.insert(t + TimeDelta::milliseconds(ms)).await.unwrap();
+        }
+        let snapshot = detector.stats_snapshot().await;
+        let mut phis = vec![];
+        for ms in [550, 600, 700] {
+            phis.push(detector.phi(t + TimeDelta::milliseconds(ms)).await.unwrap());
+        }
+
+        let view = detector.view().await;
+        assert_eq!(snapshot.mean, view.mean());
+        assert_eq!(snapshot.variance, view.variance());
+        for (ms, phi) in [550, 600, 700].into_iter().zip(phis) {
+            assert_eq!(phi, view.phi(t + TimeDelta::milliseconds(ms)).unwrap());
+        }
+        // intervals 100, 120, 80, 110, 90
+        assert_eq!(5, view.sample_count());
+        assert_eq!(Some(80.), view.percentile(0.));
+        assert_eq!(Some(100.), view.percentile(50.));
+        assert_eq!(Some(115.), view.percentile(87.5));
+        assert_eq!(Some(120.), view.percentile(100.));
+        assert_eq!(None, view.percentile(101.));
+        drop(view);
+        assert!(Detector::new(10).view().await.percentile(50.).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_phi_at_percentile() {
+        let detector = Detector::new(100);
+        let t = Local::now();
+        let mut arrived_at = t;
+        detector.insert(arrived_at).await.unwrap();
+        for i in 0..100 {
+            arrived_at += TimeDelta::milliseconds(90 + i % 21);
+            detector.insert(arrived_at).await.unwrap();
+        }
+
+        let median = detector.phi_at_percentile(50.).await.unwrap();
+        let tail = detector.phi_at_percentile(99.).await.unwrap();
+        // at the median phi is that of a heartbeat right on the mean, -log10(0.5)
+        assert!((median - 0.301).abs() < 0.05, "{}", median);
+        assert!(tail > median + 0.5, "{} vs {}", tail, median);
+        assert!(detector.phi_at_percentile(100.).await.unwrap() >= tail);
+        assert!(Detector::new(10).phi_at_percentile(50.).await.is_err());
+    }
+}